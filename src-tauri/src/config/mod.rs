@@ -1,13 +1,17 @@
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use tokio::sync::RwLock;
 
-use crate::error::Up2bResult;
+use crate::error::{ProxyError, Up2bResult};
 use crate::manager::api::Api;
 use crate::manager::smms::SMMS_API;
+use crate::manager::CompressedFormat;
+use crate::server::ServerConfig;
+use crate::util::image::{DecodeLimits, OptimizeConfig, ThumbnailConfig, WatermarkConfig};
 use crate::ManagerCode;
 
 lazy_static! {
@@ -37,12 +41,58 @@ pub enum ProxyKind {
     Socks5h { host: String, port: u32 },
 }
 
+impl ProxyKind {
+    fn host_and_port(&self) -> (&str, u32) {
+        match self {
+            ProxyKind::Http { host, port }
+            | ProxyKind::Https { host, port }
+            | ProxyKind::Socks5 { host, port }
+            | ProxyKind::Socks5h { host, port } => (host, *port),
+        }
+    }
+
+    fn scheme(&self) -> &'static str {
+        match self {
+            ProxyKind::Http { .. } => "http",
+            ProxyKind::Https { .. } => "https",
+            ProxyKind::Socks5 { .. } => "socks5",
+            ProxyKind::Socks5h { .. } => "socks5h",
+        }
+    }
+
+    pub fn url(&self) -> String {
+        let (host, port) = self.host_and_port();
+
+        format!("{}://{}:{}", self.scheme(), host, port)
+    }
+
+    /// 检查代理的主机和端口，并确认 reqwest 能够解析代理地址
+    pub fn validate(&self) -> Up2bResult<()> {
+        let (host, port) = self.host_and_port();
+
+        if host.trim().is_empty() || host.contains("://") || host.contains('/') {
+            return Err(ProxyError::InvalidHost(host.to_owned()).into());
+        }
+
+        if port == 0 || port > u16::MAX as u32 {
+            return Err(ProxyError::InvalidPort(port).into());
+        }
+
+        reqwest::Proxy::all(self.url()).map_err(|e| ProxyError::Invalid(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "UPPERCASE")]
 pub enum ManagerAuthConfigKind {
-    API {
+    Api {
         token: String,
-        api: Api,
+        api: Box<Api>,
+        /// 上传后的文件名模板，见 util::naming
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name_template: Option<String>,
     },
     Git {
         base_url: String,
@@ -51,6 +101,12 @@ pub enum ManagerAuthConfigKind {
         repository: String,
         /// 保存目录,默认为 up2b
         path: Option<String>,
+        /// 分支，为 None 时使用仓库的默认分支，gitlab 默认为 main
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+        /// 上传后的文件名模板，见 util::naming
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name_template: Option<String>,
     },
     Chevereto {
         timeout: Option<u8>,
@@ -58,6 +114,37 @@ pub enum ManagerAuthConfigKind {
         password: String,
         extra: Option<HashMap<String, String>>,
     },
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        access_key: String,
+        secret_key: String,
+        /// 对象键前缀
+        prefix: Option<String>,
+        /// 使用路径形式的地址，MinIO 等兼容服务一般需要开启
+        #[serde(default = "default_path_style")]
+        path_style: bool,
+        /// 公开访问地址，比如 CDN 域名
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        public_url: Option<String>,
+        /// 上传后的文件名模板，见 util::naming
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name_template: Option<String>,
+    },
+    Local {
+        /// 保存图片的目录，比如静态网站的 static/img
+        directory: String,
+        /// 目录对应的公开访问地址前缀
+        url_prefix: String,
+        /// 上传后的文件名模板，见 util::naming
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name_template: Option<String>,
+    },
+}
+
+fn default_path_style() -> bool {
+    true
 }
 
 fn default_image_bed() -> ManagerCode {
@@ -72,6 +159,14 @@ fn default_automatic_compression() -> bool {
     false
 }
 
+fn default_compression_margin() -> u8 {
+    5
+}
+
+fn default_upload_concurrency() -> usize {
+    3
+}
+
 /// 图床属性名应该是 ImageBedCode 的小写
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -79,10 +174,49 @@ pub struct Config {
     using: ManagerCode,
     #[serde(default = "default_automatic_compression")]
     automatic_compression: bool,
+    /// 自动压缩时的安全余量，百分比，压缩后的体积不超过图床限制的 (100 - margin)%
+    #[serde(default = "default_compression_margin")]
+    compression_margin: u8,
+    /// 上传前优化图片，每张图片都会处理
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimize: Option<OptimizeConfig>,
+    /// 上传前添加水印
+    #[serde(skip_serializing_if = "Option::is_none")]
+    watermark: Option<WatermarkConfig>,
+    /// 为没有缩略图的图床生成缩略图
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<ThumbnailConfig>,
+    /// 解码图片时的限制，未配置时使用默认限制
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decode_limits: Option<DecodeLimits>,
     #[serde(default = "default_use_proxy")]
     use_proxy: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     proxy: Option<ProxyKind>,
+    /// 批量上传时的最大并发数
+    #[serde(default = "default_upload_concurrency")]
+    upload_concurrency: usize,
+    /// 镜像图床，镜像上传时图片会同时上传到正在使用的图床和这些图床
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mirrors: Vec<ManagerCode>,
+    /// 备用图床，上传遇到网络错误或服务端错误时按顺序使用这些图床重试
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fallbacks: Vec<ManagerCode>,
+    /// 按图床覆盖默认的压缩格式，未启用 compress 时只读写不使用
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    compressed_formats: HashMap<ManagerCode, CompressedFormat>,
+    /// 上传前去除图片元数据的图床
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    strip_metadata: Vec<ManagerCode>,
+    /// 自动上传新图片的目录，比如系统截图目录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    watch_dirs: Vec<PathBuf>,
+    /// 兼容 PicGo 的本地上传服务
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<ServerConfig>,
+    /// 自定义链接模板，键为模板名，同名时覆盖内置模板
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    link_templates: HashMap<String, String>,
     auth_config: HashMap<ManagerCode, ManagerAuthConfigKind>,
 }
 
@@ -91,8 +225,21 @@ impl Default for Config {
         Config {
             using: Default::default(),
             automatic_compression: Default::default(),
+            compression_margin: default_compression_margin(),
+            optimize: None,
+            watermark: None,
+            thumbnail: None,
+            decode_limits: None,
             use_proxy: Default::default(),
             proxy: None,
+            upload_concurrency: default_upload_concurrency(),
+            mirrors: Vec::new(),
+            fallbacks: Vec::new(),
+            compressed_formats: HashMap::default(),
+            strip_metadata: Vec::new(),
+            watch_dirs: Vec::new(),
+            server: None,
+            link_templates: HashMap::default(),
             auth_config: HashMap::default(),
         }
     }
@@ -111,6 +258,109 @@ impl Config {
         self.automatic_compression
     }
 
+    #[cfg(feature = "compress")]
+    pub fn compression_margin(&self) -> u8 {
+        self.compression_margin
+    }
+
+    #[cfg(feature = "compress")]
+    pub fn optimize(&self) -> Option<&OptimizeConfig> {
+        self.optimize.as_ref()
+    }
+
+    #[cfg(feature = "compress")]
+    pub fn watermark(&self) -> Option<&WatermarkConfig> {
+        self.watermark.as_ref()
+    }
+
+    #[cfg(feature = "compress")]
+    pub fn thumbnail(&self) -> Option<&ThumbnailConfig> {
+        self.thumbnail.as_ref()
+    }
+
+    pub fn decode_limits(&self) -> DecodeLimits {
+        self.decode_limits.clone().unwrap_or_default()
+    }
+
+    pub fn upload_concurrency(&self) -> usize {
+        self.upload_concurrency
+    }
+
+    /// 镜像上传的所有图床，正在使用的图床在最前面，不包含重复的图床
+    pub fn mirror_set(&self) -> Vec<ManagerCode> {
+        let mut managers = vec![self.using.clone()];
+
+        for m in self.mirrors.iter() {
+            if !managers.contains(m) {
+                managers.push(m.clone());
+            }
+        }
+
+        managers
+    }
+
+    pub fn fallbacks(&self) -> &[ManagerCode] {
+        &self.fallbacks
+    }
+
+    #[cfg(feature = "compress")]
+    pub fn compressed_format(&self, manager: &ManagerCode) -> Option<&CompressedFormat> {
+        self.compressed_formats.get(manager)
+    }
+
+    pub fn strip_metadata(&self, manager: &ManagerCode) -> bool {
+        self.strip_metadata.contains(manager)
+    }
+
+    /// 上传前处理图片的配置的哈希，配置改变后处理结果可能不同，相同的图片需要重新上传
+    pub fn pipeline_hash(&self, manager: &ManagerCode) -> String {
+        let mut pipeline = serde_json::Map::new();
+
+        pipeline.insert(
+            "strip_metadata".to_owned(),
+            self.strip_metadata(manager).into(),
+        );
+
+        #[cfg(feature = "compress")]
+        {
+            pipeline.insert(
+                "automatic_compression".to_owned(),
+                self.automatic_compression.into(),
+            );
+            pipeline.insert(
+                "compression_margin".to_owned(),
+                self.compression_margin.into(),
+            );
+            pipeline.insert(
+                "compressed_format".to_owned(),
+                serde_json::json!(self.compressed_format(manager)),
+            );
+            pipeline.insert("optimize".to_owned(), serde_json::json!(self.optimize));
+            pipeline.insert("watermark".to_owned(), serde_json::json!(self.watermark));
+            pipeline.insert("thumbnail".to_owned(), serde_json::json!(self.thumbnail));
+        }
+
+        hex::encode(Sha256::digest(
+            serde_json::Value::Object(pipeline).to_string(),
+        ))
+    }
+
+    pub fn watch_dirs(&self) -> &[PathBuf] {
+        &self.watch_dirs
+    }
+
+    pub fn server(&self) -> ServerConfig {
+        self.server.clone().unwrap_or_default()
+    }
+
+    pub fn set_server(&mut self, server: ServerConfig) {
+        self.server = Some(server);
+    }
+
+    pub fn link_templates(&self) -> &HashMap<String, String> {
+        &self.link_templates
+    }
+
     // pub fn enable_automatic_compression(&mut self) {
     //     if !self.automatic_compression {
     //         self.automatic_compression = true;
@@ -122,35 +372,48 @@ impl Config {
     //         self.automatic_compression = false;
     //     }
     // }
-    //
-    // pub fn enable_proxy(&mut self) -> Result<()> {
-    //     if self.use_proxy {
-    //         return Ok(());
-    //     }
-    //
-    //     if self.proxy.is_none() {
-    //         return Err(Error::Proxy(ProxyError::Null));
-    //     }
-    //
-    //     self.use_proxy = true;
-    //
-    //     Ok(())
-    // }
-    //
-    // pub fn disable_proxy(&mut self) {
-    //     if self.use_proxy {
-    //         self.use_proxy = false;
-    //     }
-    // }
-    //
-    // pub fn proxy(&self) -> Option<&ProxyKind> {
-    //     self.proxy.as_ref()
-    // }
-    //
-    // pub fn set_proxy(&mut self, proxy: ProxyKind) {
-    //     self.proxy = Some(proxy);
-    // }
-    //
+
+    pub fn enable_proxy(&mut self) -> Up2bResult<()> {
+        let proxy = match &self.proxy {
+            None => return Err(ProxyError::Null.into()),
+            Some(p) => p,
+        };
+
+        proxy.validate()?;
+
+        self.use_proxy = true;
+
+        Ok(())
+    }
+
+    pub fn disable_proxy(&mut self) {
+        if self.use_proxy {
+            self.use_proxy = false;
+        }
+    }
+
+    /// 已配置的代理，不论是否启用
+    pub fn proxy(&self) -> Option<&ProxyKind> {
+        self.proxy.as_ref()
+    }
+
+    /// 请求时实际使用的代理，未启用代理时为 None
+    pub fn active_proxy(&self) -> Option<&ProxyKind> {
+        if self.use_proxy {
+            self.proxy.as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn set_proxy(&mut self, proxy: ProxyKind) -> Up2bResult<()> {
+        proxy.validate()?;
+
+        self.proxy = Some(proxy);
+
+        Ok(())
+    }
+
     pub fn auth_config(&self) -> &HashMap<ManagerCode, ManagerAuthConfigKind> {
         &self.auth_config
     }
@@ -177,17 +440,20 @@ fn read_config() -> Up2bResult<Option<Config>> {
 
     let mut config: Config = toml::from_str(&config_str)?;
 
-    // 加载 smms 示例配置
-    if config.auth_config.get(&ManagerCode::Smms).is_none() {
-        config.auth_config.insert(
-            ManagerCode::Smms,
-            ManagerAuthConfigKind::API {
-                token: "".to_owned(),
-                api: SMMS_API.clone(),
-            },
-        );
+    if let Some(optimize) = &config.optimize {
+        optimize.validate()?;
     }
 
+    // 加载 smms 示例配置
+    config
+        .auth_config
+        .entry(ManagerCode::Smms)
+        .or_insert_with(|| ManagerAuthConfigKind::Api {
+            token: "".to_owned(),
+            api: Box::new(SMMS_API.clone()),
+            name_template: None,
+        });
+
     Ok(Some(config))
 }
 
@@ -200,3 +466,38 @@ pub fn write_config(config: &Config) -> Up2bResult<()> {
 
     Ok(fs::write(CONFIG_FILE.to_owned(), config_str)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_hash_changes_with_processing_config() {
+        let mut config = Config::default();
+        let before = config.pipeline_hash(&ManagerCode::Github);
+
+        assert_eq!(before, config.pipeline_hash(&ManagerCode::Github));
+
+        config.strip_metadata.push(ManagerCode::Github);
+        let stripped = config.pipeline_hash(&ManagerCode::Github);
+        assert_ne!(before, stripped);
+        // 其他图床的处理配置没有变化
+        assert_eq!(before, config.pipeline_hash(&ManagerCode::Smms));
+    }
+
+    #[test]
+    fn compressed_formats_survive_round_trip() {
+        // 未启用 compress 时保存配置也不能丢失压缩格式
+        let mut config = Config::default();
+        config
+            .compressed_formats
+            .insert(ManagerCode::Github, CompressedFormat::Avif);
+
+        let config: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+
+        assert!(matches!(
+            config.compressed_formats.get(&ManagerCode::Github),
+            Some(CompressedFormat::Avif)
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tauri::WebviewWindow;
+use tokio::fs;
+
+use crate::{
+    manager::UploadResult,
+    upload::{is_url, upload_image},
+    using_uploader,
+    util::{time::now, url::percent_decode},
+    Up2bError, Up2bResult,
+};
+
+lazy_static! {
+    /// `![alt](path "title")` 或 `![alt](<path with spaces>)`
+    static ref MARKDOWN_IMAGE: Regex =
+        Regex::new(r#"!\[[^\]]*\]\(\s*(?:<([^>\n]+)>|([^)\s]+))(?:\s+(?:"[^"\n]*"|'[^'\n]*'))?\s*\)"#)
+            .unwrap();
+    /// `<img src="path">`，src 前必须是空白，不匹配 `data-src` 等属性
+    static ref HTML_IMAGE: Regex =
+        Regex::new(r#"(?i)<img\b[^>]*?\ssrc\s*=\s*(?:"([^"]+)"|'([^']+)')"#).unwrap();
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RewriteOptions {
+    /// Markdown 或 HTML 文档
+    pub path: PathBuf,
+    /// 只生成差异，不上传图片也不修改文档
+    #[serde(default)]
+    pub dry_run: bool,
+    /// 修改前备份原文档
+    #[serde(default = "default_backup")]
+    pub backup: bool,
+    /// 相同内容的图片已上传过时仍重新上传
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn default_backup() -> bool {
+    true
+}
+
+/// 文档中的一张本地图片
+#[derive(Debug, Serialize)]
+pub struct RewrittenImage {
+    /// 文档中原来的图片路径
+    source: String,
+    /// 上传后的链接，dry_run 或上传失败时为 None
+    url: Option<String>,
+    error: Option<Up2bError>,
+    code: Option<String>,
+}
+
+impl RewrittenImage {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn error(&self) -> Option<(&str, &Up2bError)> {
+        match (&self.code, &self.error) {
+            (Some(c), Some(e)) => Some((c, e)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RewriteReport {
+    path: PathBuf,
+    images: Vec<RewrittenImage>,
+    /// 修改前后有变化的行
+    diff: String,
+    /// 原文档的备份，未修改文档时为 None
+    backup: Option<PathBuf>,
+}
+
+impl RewriteReport {
+    pub fn images(&self) -> &[RewrittenImage] {
+        &self.images
+    }
+
+    pub fn diff(&self) -> &str {
+        &self.diff
+    }
+
+    pub fn backup(&self) -> Option<&Path> {
+        self.backup.as_deref()
+    }
+}
+
+/// 行内代码的位置，反引号数量相同的一对反引号之间为代码，没有闭合的反引号按普通字符处理
+fn inline_code_ranges(text: &str, base: usize, ranges: &mut Vec<Range<usize>>) {
+    let bytes = text.as_bytes();
+    let run_at = |i: usize| bytes[i..].iter().take_while(|b| **b == b'`').count();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+
+        let n = run_at(i);
+        let mut j = i + n;
+        let mut end = None;
+
+        while j < bytes.len() {
+            if bytes[j] != b'`' {
+                j += 1;
+                continue;
+            }
+
+            let m = run_at(j);
+            if m == n {
+                end = Some(j + m);
+                break;
+            }
+            j += m;
+        }
+
+        match end {
+            Some(end) => {
+                ranges.push(base + i..base + end);
+                i = end;
+            }
+            None => i += n,
+        }
+    }
+}
+
+/// Markdown 中代码块和行内代码的位置，其中的图片只是示例，不应上传
+fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    // 正在读取的代码块的围栏字符、长度和开始位置
+    let mut fence: Option<(char, usize, usize)> = None;
+    let mut text_start = 0;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let run = marker.map_or(0, |c| trimmed.chars().take_while(|x| *x == c).count());
+
+        match fence {
+            None if indent < 4 && run >= 3 => {
+                inline_code_ranges(&content[text_start..offset], text_start, &mut ranges);
+                fence = Some((marker.unwrap(), run, offset));
+            }
+            Some((c, n, start))
+                if indent < 4
+                    && marker == Some(c)
+                    && run >= n
+                    && trimmed[run..].trim().is_empty() =>
+            {
+                ranges.push(start..offset + line.len());
+                fence = None;
+                text_start = offset + line.len();
+            }
+            _ => {}
+        }
+
+        offset += line.len();
+    }
+
+    match fence {
+        // 没有闭合的代码块直到文档结尾
+        Some((_, _, start)) => ranges.push(start..content.len()),
+        None => inline_code_ranges(&content[text_start..], text_start, &mut ranges),
+    }
+
+    ranges
+}
+
+/// 文档中图片路径的位置和内容，markdown 为 true 时忽略代码块和行内代码中的图片
+fn find_images(content: &str, markdown: bool) -> Vec<(Range<usize>, &str)> {
+    let code = match markdown {
+        true => code_ranges(content),
+        false => Vec::new(),
+    };
+
+    let mut images: Vec<(Range<usize>, &str)> = [&*MARKDOWN_IMAGE, &*HTML_IMAGE]
+        .iter()
+        .flat_map(|re| re.captures_iter(content))
+        .filter(|c| {
+            let start = c.get(0).unwrap().start();
+            !code.iter().any(|r| r.contains(&start))
+        })
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| (m.range(), m.as_str()))
+        .collect();
+
+    images.sort_by_key(|(r, _)| r.start);
+
+    images
+}
+
+/// 扩展名为 html 或 htm 的文档按 HTML 处理，其他按 Markdown 处理
+fn is_markdown(path: &Path) -> bool {
+    !path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"))
+}
+
+/// 是否为本地图片，链接、data url 和协议相对链接都不是本地图片
+fn is_local(source: &str) -> bool {
+    !(is_url(source)
+        || source.starts_with("data:")
+        || source.starts_with("//")
+        || source.contains("://"))
+}
+
+/// 相对路径相对于文档所在的目录，路径中可能有 url 编码的字符
+fn resolve_image_path(document: &Path, source: &str) -> PathBuf {
+    let source = source.split(['?', '#']).next().unwrap_or(source);
+    let dir = document.parent().unwrap_or(Path::new(""));
+
+    let path = dir.join(source);
+    if path.exists() {
+        return path;
+    }
+
+    dir.join(percent_decode(source))
+}
+
+/// 逐行比较，只输出有变化的行，修改图片链接不会改变行数
+fn diff_lines(path: &Path, old: &str, new: &str) -> String {
+    let mut diff = String::new();
+
+    for (index, (o, n)) in old.lines().zip(new.lines()).enumerate() {
+        if o != n {
+            diff.push_str(&format!(
+                "@@ {}:{} @@\n-{}\n+{}\n",
+                path.display(),
+                index + 1,
+                o,
+                n
+            ));
+        }
+    }
+
+    diff
+}
+
+/// 备份文件名为 `<文档名>.bak`，已存在时加上时间戳
+async fn backup_document(path: &Path, content: &str) -> Up2bResult<PathBuf> {
+    let mut backup = PathBuf::from(format!("{}.bak", path.display()));
+
+    if backup.exists() {
+        backup = PathBuf::from(format!("{}.{}.bak", path.display(), now()?.as_secs()));
+    }
+
+    fs::write(&backup, content).await?;
+
+    Ok(backup)
+}
+
+/// 上传文档中的本地图片，并将图片路径替换为上传后的链接，上传失败的图片保持原样
+pub async fn rewrite_document(
+    options: RewriteOptions,
+    window: Option<WebviewWindow>,
+) -> Up2bResult<RewriteReport> {
+    let path = options.path;
+    let content = fs::read_to_string(&path).await?;
+
+    let references: Vec<(Range<usize>, &str)> = find_images(&content, is_markdown(&path))
+        .into_iter()
+        .filter(|(_, s)| is_local(s))
+        .collect();
+
+    info!("文档中有 {} 张本地图片：{:?}", references.len(), path);
+
+    // 同一张图片在文档中可能出现多次，只上传一次
+    let mut uploaded: HashMap<&str, RewrittenImage> = HashMap::new();
+
+    let uploader = match options.dry_run {
+        true => None,
+        false => Some(using_uploader().await?),
+    };
+
+    for (index, (_, source)) in references.iter().enumerate() {
+        if uploaded.contains_key(source) {
+            continue;
+        }
+
+        let mut image = RewrittenImage {
+            source: source.to_string(),
+            url: None,
+            error: None,
+            code: None,
+        };
+
+        let image_path = resolve_image_path(&path, source);
+
+        if !image_path.exists() {
+            let e = Up2bError::Other(format!("图片不存在：{:?}", image_path));
+            image.code = Some(e.as_string());
+            image.error = Some(e);
+        } else if let Some((manager, uploader)) = &uploader {
+            match upload_image(
+                uploader.as_ref(),
+                manager,
+                window.clone(),
+                index as u32,
+                &image_path,
+                options.force,
+            )
+            .await
+            {
+                UploadResult::Response(r) => image.url = Some(r.url),
+                UploadResult::Error { detail, code } => {
+                    image.code = Some(code);
+                    image.error = Some(detail);
+                }
+            }
+        }
+
+        uploaded.insert(*source, image);
+    }
+
+    // 从后往前替换，避免前面的替换改变后面的位置
+    let mut new_content = content.clone();
+    for (range, source) in references.iter().rev() {
+        let image = &uploaded[source];
+
+        let replacement = match (&image.url, options.dry_run) {
+            (Some(url), _) => url.clone(),
+            (None, true) if image.error.is_none() => format!("<uploaded:{}>", source),
+            _ => continue,
+        };
+
+        new_content.replace_range(range.clone(), &replacement);
+    }
+
+    let diff = diff_lines(&path, &content, &new_content);
+
+    let backup = if options.dry_run || new_content == content {
+        None
+    } else {
+        let backup = match options.backup {
+            true => Some(backup_document(&path, &content).await?),
+            false => None,
+        };
+
+        fs::write(&path, &new_content).await?;
+        info!("已替换文档中的本地图片：{:?}", path);
+
+        backup
+    };
+
+    let images = references
+        .iter()
+        .filter_map(|(_, source)| uploaded.remove(source))
+        .collect();
+
+    Ok(RewriteReport {
+        path,
+        images,
+        diff,
+        backup,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(content: &str, markdown: bool) -> Vec<&str> {
+        find_images(content, markdown)
+            .into_iter()
+            .map(|(_, s)| s)
+            .collect()
+    }
+
+    #[test]
+    fn markdown_images() {
+        assert_eq!(
+            sources(
+                r#"![a](a.png) ![b]( <b c.png> ) ![c](c.png "title") ![d](d.png 'title')"#,
+                true
+            ),
+            ["a.png", "b c.png", "c.png", "d.png"]
+        );
+    }
+
+    #[test]
+    fn html_src_is_not_data_src() {
+        assert_eq!(
+            sources(
+                r#"<img data-src="lazy.png" src="a.png"> <IMG SRC='b.png'>"#,
+                false
+            ),
+            ["a.png", "b.png"]
+        );
+        assert!(sources(r#"<img data-src="lazy.png">"#, false).is_empty());
+    }
+
+    #[test]
+    fn skips_code_spans() {
+        assert_eq!(
+            sources(
+                "`![a](a.png)` ``![b](b.png) ` `` ![c](c.png) `![d](d.png)",
+                true
+            ),
+            ["c.png", "d.png"]
+        );
+    }
+
+    #[test]
+    fn skips_fenced_code() {
+        let content = "![a](a.png)\n```md\n![b](b.png)\n````\n~~~\n<img src=\"c.png\">\n~~~\n![d](d.png)\n```\n![e](e.png)";
+        assert_eq!(sources(content, true), ["a.png", "d.png"]);
+    }
+
+    #[test]
+    fn html_documents_keep_backticks() {
+        assert_eq!(
+            sources(r#"<p>`</p><img src="a.png"><p>`</p>"#, false),
+            ["a.png"]
+        );
+        assert!(is_markdown(Path::new("a.md")));
+        assert!(!is_markdown(Path::new("a.HTML")));
+    }
+}
@@ -14,6 +14,8 @@ pub enum ConfigError {
     IsNull(String),
     #[error("config: {0} type is error")]
     Type(String),
+    #[error("config: optimize format {0} is not supported, use JPEG, WEBP, AVIF or PNG")]
+    OptimizeFormat(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -39,7 +41,12 @@ pub enum Up2bError {
     Config(#[from] ConfigError),
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
-    #[error("图床 {0} 不可上传此体积图片：path={1}, size={3}M > {2}M")]
+    /// 图床名、图片路径、体积限制和图片体积，体积的单位均为字节
+    #[error(
+        "图床 {0} 不可上传此体积图片：path={1}, size={size:.1}M > {max:.1}M",
+        size = bytes_to_mb(*.3),
+        max = bytes_to_mb(*.2)
+    )]
     OverSize(String, String, u64, u64),
     #[error(transparent)]
     Time(#[from] SystemTimeError),
@@ -84,6 +91,19 @@ pub enum Up2bError {
 
     #[error(transparent)]
     Git(#[from] GitError),
+
+    #[error(transparent)]
+    S3(#[from] S3Error),
+
+    #[error(transparent)]
+    Token(#[from] TokenError),
+
+    #[error(transparent)]
+    Notify(#[from] notify::Error),
+}
+
+fn bytes_to_mb(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
 }
 
 impl Up2bError {
@@ -91,13 +111,37 @@ impl Up2bError {
         match self {
             Self::OverSize(_, _, _, _) => "OVER_SIZE".to_owned(),
             Self::Path(e) => e.as_str().to_owned(),
+            Self::Token(e) => e.as_str().to_owned(),
             Self::Upload(e) => match e {
                 UploadError::Repeat(_) => "REPEATED".to_owned(),
+                UploadError::UnsupportedFormat => "UNSUPPORTED_FORMAT".to_owned(),
+                UploadError::Corrupted => "CORRUPTED".to_owned(),
+                UploadError::TooLarge(_) => "TOO_LARGE".to_owned(),
                 _ => "UNKOWN".to_owned(),
             },
             _ => "UNKOWN".to_owned(),
         }
     }
+
+    /// 是否为网络错误、服务端错误或限流，这类错误可以换一个图床重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqeust(e) => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_request()
+                    || e.status().is_some_and(is_retryable_status)
+            }
+            Self::Status(s) => is_retryable_status(*s),
+            Self::ConnectionClosedBeforeMessageCompleted => true,
+            _ => false,
+        }
+    }
+}
+
+/// 5xx 和 429 可以重试，其他 4xx 换一个图床也不会成功
+pub fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
 }
 
 impl Serialize for Up2bError {
@@ -123,6 +167,12 @@ pub enum CheveretoError {
 pub enum ProxyError {
     #[error("代理配置为空")]
     Null,
+    #[error("代理主机无效：{0}")]
+    InvalidHost(String),
+    #[error("代理端口无效：{0}")]
+    InvalidPort(u32),
+    #[error("代理地址无效：{0}")]
+    Invalid(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -145,6 +195,12 @@ pub enum UploadError {
     Error(String),
     #[error("{0}")]
     Repeat(String),
+    #[error("不是支持的图片格式")]
+    UnsupportedFormat,
+    #[error("图片数据损坏")]
+    Corrupted,
+    #[error("图片体积超过下载限制：{0} MB")]
+    TooLarge(u64),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -176,17 +232,72 @@ impl GitError {
     pub fn as_str(&self) -> &str {
         match self {
             GitError::NotFound => "NOT_FOUND",
-            GitError::Other(s) => &s,
+            GitError::Other(s) => s,
         }
     }
 }
 
-impl Into<GitError> for String {
-    fn into(self) -> GitError {
-        if self == "Not Found" {
+impl From<String> for GitError {
+    fn from(message: String) -> Self {
+        if message == "Not Found" {
             return GitError::NotFound;
         }
 
-        GitError::Other(self)
+        GitError::Other(message)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum S3Error {
+    #[error("{code}: {message}")]
+    Response { code: String, message: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    #[error("token 无效：{0}")]
+    Invalid(String),
+    #[error("token 没有 {0} 的写入权限")]
+    NoPermission(String),
+}
+
+impl TokenError {
+    pub fn as_str(&self) -> &str {
+        match self {
+            TokenError::Invalid(_) => "INVALID_TOKEN",
+            TokenError::NoPermission(_) => "NO_PERMISSION",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn over_size_reports_one_decimal_mb() {
+        let e = Up2bError::OverSize(
+            "SMMS".to_owned(),
+            "a.png".to_owned(),
+            5 * 1024 * 1024,
+            5_400_000,
+        );
+        assert_eq!(
+            e.to_string(),
+            "图床 SMMS 不可上传此体积图片：path=a.png, size=5.1M > 5.0M"
+        );
+    }
+
+    #[test]
+    fn only_server_errors_and_rate_limits_are_retryable() {
+        assert!(Up2bError::Status(StatusCode::INTERNAL_SERVER_ERROR).is_retryable());
+        assert!(Up2bError::Status(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(Up2bError::Status(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+
+        assert!(!Up2bError::Status(StatusCode::BAD_REQUEST).is_retryable());
+        assert!(!Up2bError::Status(StatusCode::UNAUTHORIZED).is_retryable());
+        assert!(!Up2bError::Status(StatusCode::NOT_FOUND).is_retryable());
     }
 }
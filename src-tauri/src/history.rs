@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::RwLock};
+
+use crate::{
+    config::APP_CONFIG_DIR,
+    manager::{ImageItem, ManagerCode},
+    util::{hash::sha256, time::now},
+    Up2bResult,
+};
+
+lazy_static! {
+    static ref HISTORY_FILE: PathBuf = APP_CONFIG_DIR.join("history.json");
+    /// 首次访问时从文件中加载
+    static ref HISTORY: RwLock<Option<Vec<HistoryItem>>> = RwLock::new(None);
+}
+
+/// 一条上传记录，与图床能否获取图片列表无关
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryItem {
+    id: u64,
+    manager: ManagerCode,
+    url: String,
+    deleted_id: String,
+    thumb: Option<String>,
+    /// 缩略图上传到图床时的删除 id，本地缩略图或图床自带的缩略图为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thumb_deleted_id: Option<String>,
+    /// 上传前的文件名
+    filename: String,
+    /// 文件大小，单位为字节
+    size: u64,
+    /// 文件内容的 sha256
+    hash: String,
+    /// 上传时图片处理配置的哈希，与 hash 一起判断图片是否已上传过
+    #[serde(default)]
+    pipeline_hash: String,
+    /// 上传时间，unix 时间戳，单位为秒
+    created_at: u64,
+}
+
+impl HistoryItem {
+    pub fn manager(&self) -> &ManagerCode {
+        &self.manager
+    }
+
+    pub fn deleted_id(&self) -> &str {
+        &self.deleted_id
+    }
+
+    pub fn thumb(&self) -> Option<&str> {
+        self.thumb.as_deref()
+    }
+
+    pub fn thumb_deleted_id(&self) -> Option<&str> {
+        self.thumb_deleted_id.as_deref()
+    }
+}
+
+/// 查询条件，keyword 匹配文件名和链接，不区分大小写
+#[derive(Debug, Deserialize, Default)]
+pub struct HistoryQuery {
+    manager: Option<ManagerCode>,
+    keyword: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// 计算文件内容的 sha256，分块读取，不会将整个文件读入内存
+pub async fn file_hash(path: &Path) -> Up2bResult<(String, u64)> {
+    let mut file = fs::File::open(path).await?;
+
+    Ok(sha256(&mut file).await?)
+}
+
+async fn load() -> Up2bResult<Vec<HistoryItem>> {
+    if !HISTORY_FILE.exists() {
+        return Ok(Vec::new());
+    }
+
+    let s = fs::read_to_string(HISTORY_FILE.as_path()).await?;
+
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// 先写入临时文件再重命名，写入过程中退出也不会损坏已有的记录
+async fn save(items: &[HistoryItem]) -> Up2bResult<()> {
+    let s = serde_json::to_string(items)?;
+
+    let temp = HISTORY_FILE.with_extension("json.tmp");
+    fs::write(&temp, s).await?;
+
+    Ok(fs::rename(&temp, HISTORY_FILE.as_path()).await?)
+}
+
+/// 在读写锁内对上传记录进行操作，changed 为 true 时写入文件
+async fn with_history<T, F>(f: F) -> Up2bResult<T>
+where
+    F: FnOnce(&mut Vec<HistoryItem>) -> (T, bool),
+{
+    let mut guard = HISTORY.write().await;
+
+    if guard.is_none() {
+        *guard = Some(load().await?);
+    }
+
+    let items = guard.as_mut().unwrap();
+
+    let (result, changed) = f(items);
+
+    if changed {
+        save(items).await?;
+    }
+
+    Ok(result)
+}
+
+/// 记录上传成功的图片，image_path 为上传的本地文件，hash 和 size 由 [`file_hash`] 计算，
+/// pipeline_hash 由 [`Config::pipeline_hash`](crate::config::Config::pipeline_hash) 计算
+pub async fn record(
+    manager: &ManagerCode,
+    image: &ImageItem,
+    thumb_deleted_id: Option<String>,
+    image_path: &Path,
+    (hash, size): (String, u64),
+    pipeline_hash: String,
+) -> Up2bResult<()> {
+    let filename = image_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let created_at = now()?.as_secs();
+
+    with_history(|items| {
+        let id = items.iter().map(|i| i.id).max().unwrap_or_default() + 1;
+
+        items.push(HistoryItem {
+            id,
+            manager: manager.clone(),
+            url: image.url.clone(),
+            deleted_id: image.deleted_id().to_owned(),
+            thumb: image.thumb().map(|t| t.to_owned()),
+            thumb_deleted_id,
+            filename,
+            size,
+            hash,
+            pipeline_hash,
+            created_at,
+        });
+
+        ((), true)
+    })
+    .await
+}
+
+/// 按上传时间倒序返回符合条件的记录
+pub async fn query(query: HistoryQuery) -> Up2bResult<Vec<HistoryItem>> {
+    let keyword = query.keyword.map(|k| k.to_lowercase());
+
+    with_history(|items| {
+        let result: Vec<HistoryItem> = items
+            .iter()
+            .rev()
+            .filter(|i| query.manager.as_ref().is_none_or(|m| &i.manager == m))
+            .filter(|i| {
+                keyword.as_ref().is_none_or(|k| {
+                    i.filename.to_lowercase().contains(k) || i.url.to_lowercase().contains(k)
+                })
+            })
+            .skip(query.offset.unwrap_or_default())
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+
+        (result, false)
+    })
+    .await
+}
+
+/// 查找同一图床中内容相同且处理配置相同的图片，有多条记录时使用最近的一条
+pub async fn find_by_hash(
+    manager: &ManagerCode,
+    hash: &str,
+    pipeline_hash: &str,
+) -> Up2bResult<Option<ImageItem>> {
+    with_history(|items| {
+        let image = items
+            .iter()
+            .rev()
+            .find(|i| &i.manager == manager && i.hash == hash && i.pipeline_hash == pipeline_hash)
+            .map(|i| ImageItem::new(i.url.clone(), i.deleted_id.clone(), i.thumb.clone()));
+
+        (image, false)
+    })
+    .await
+}
+
+pub async fn get(id: u64) -> Up2bResult<Option<HistoryItem>> {
+    with_history(|items| (items.iter().find(|i| i.id == id).cloned(), false)).await
+}
+
+/// 删除记录，返回被删除的记录
+pub async fn remove(id: u64) -> Up2bResult<Option<HistoryItem>> {
+    with_history(|items| match items.iter().position(|i| i.id == id) {
+        Some(index) => (Some(items.remove(index)), true),
+        None => (None, false),
+    })
+    .await
+}
+
+/// 图片从图床中删除后，删除其所有记录，避免去重时返回已失效的链接。
+///
+/// 返回被删除的记录，用于删除其缩略图
+pub async fn remove_by_deleted_id(
+    manager: &ManagerCode,
+    deleted_id: &str,
+) -> Up2bResult<Vec<HistoryItem>> {
+    with_history(|items| {
+        let (removed, kept): (Vec<HistoryItem>, Vec<HistoryItem>) = items
+            .drain(..)
+            .partition(|i| &i.manager == manager && i.deleted_id == deleted_id);
+
+        *items = kept;
+
+        let changed = !removed.is_empty();
+        (removed, changed)
+    })
+    .await
+}
+
+/// 图片链接与上传时生成的缩略图的对应关系
+pub async fn thumbnails(manager: &ManagerCode) -> Up2bResult<HashMap<String, String>> {
+    with_history(|items| {
+        let thumbs = items
+            .iter()
+            .filter(|i| &i.manager == manager)
+            .filter_map(|i| i.thumb.as_ref().map(|t| (i.url.clone(), t.clone())))
+            .collect();
+
+        (thumbs, false)
+    })
+    .await
+}
+
+/// 清空记录，manager 为 None 时清空所有图床的记录
+pub async fn clear(manager: Option<ManagerCode>) -> Up2bResult<()> {
+    with_history(|items| {
+        match manager {
+            None => items.clear(),
+            Some(m) => items.retain(|i| i.manager != m),
+        }
+
+        ((), true)
+    })
+    .await
+}
@@ -1,102 +1,197 @@
-use std::{
-    pin::Pin,
-    sync::Mutex,
-    task::{Context, Poll},
-};
+use std::sync::Mutex;
 
+use base64::{engine::general_purpose, Engine as _};
 use bytes::Bytes;
-use futures::stream::Stream;
+use futures::stream::{self, Stream, StreamExt};
 use read_progress_stream::ReadProgressStream;
-use reqwest::{header::CONTENT_TYPE, RequestBuilder, Response};
-use serde_json::Value;
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    RequestBuilder, Response,
+};
+use serde_json::{Map, Value};
 use tauri::{Emitter, WebviewWindow};
+use tokio::{fs::File, io::AsyncReadExt};
 
 use crate::Up2bResult;
 
 use super::ProgressPayload;
 
-struct BytesStream {
-    data: Vec<u8>,
-    chunk_size: usize,
-    cursor: usize,
-}
-
-impl BytesStream {
-    fn new(data: Vec<u8>, chunk_size: usize) -> Self {
-        Self {
-            data,
-            chunk_size,
-            cursor: 0,
+/// 每次读取的文件大小，必须是 3 的倍数，使每块编码后的 base64 可以直接拼接
+const BASE64_CHUNK_SIZE: usize = 48 * 1024;
+
+/// 读取文件时进行 base64 编码，不需要将整个文件读入内存
+fn base64_stream(file: File) -> impl Stream<Item = std::io::Result<Bytes>> {
+    stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0u8; BASE64_CHUNK_SIZE];
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match file.read(&mut buf[filled..]).await {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) => return Some((Err(e), None)),
+            }
         }
-    }
-}
-impl Stream for BytesStream {
-    type Item = std::result::Result<Bytes, std::io::Error>;
-
-    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let len = self.data.len();
-        if self.cursor >= len {
-            Poll::Ready(None)
-        } else {
-            let data = std::mem::replace(&mut self.data, Vec::new());
-
-            let end = (self.cursor + self.chunk_size).min(data.len());
-
-            let chunk = &data[self.cursor..end].to_vec();
 
-            self.cursor = end;
+        if filled == 0 {
+            return None;
+        }
 
-            self.data = data;
+        let encoded = general_purpose::STANDARD.encode(&buf[..filled]);
 
-            let bytes = Bytes::copy_from_slice(chunk);
+        // 没有读满说明文件已读完
+        let next = if filled < buf.len() { None } else { Some(file) };
 
-            Poll::Ready(Some(Ok(bytes)))
-        }
-    }
+        Some((Ok(Bytes::from(encoded)), next))
+    })
 }
 
-pub async fn bytes_to_body(
-    id: u32,
-    window: WebviewWindow,
-    bytes: Vec<u8>,
-) -> Up2bResult<reqwest::Body> {
-    let size = bytes.len();
-
-    let window = Mutex::new(window);
-
-    Ok(reqwest::Body::wrap_stream(ReadProgressStream::new(
-        BytesStream::new(bytes, 8 * 1024),
-        Box::new(move |_, progress| {
-            let _ = window.lock().unwrap().emit(
-                "upload://progress",
-                ProgressPayload {
-                    id,
-                    progress,
-                    total: size as u64,
-                },
-            );
-        }),
-    )))
+/// size 字节的数据编码为带填充的 base64 后的长度
+fn base64_len(size: u64) -> u64 {
+    size.div_ceil(3) * 4
 }
 
+/// 以流的形式发送 json 请求体，图片的 base64 作为 key 对应的值
 pub async fn upload(
     request_builder: RequestBuilder,
     window: Option<&WebviewWindow>,
-    body: Value,
     id: u32,
+    form: Map<String, Value>,
+    key: &str,
+    file: File,
 ) -> Up2bResult<Response> {
-    let builder = match window {
-        None => request_builder.json(&body),
-        Some(w) => {
-            let stream = bytes_to_body(id, w.clone(), serde_json::to_vec(&body)?).await?;
+    let file_size = file.metadata().await?.len();
+
+    // 除图片外的请求体，去掉最后的 `}` 后拼接图片
+    let mut prefix = serde_json::to_string(&Value::Object(form.clone()))?;
+    prefix.pop();
+    if !form.is_empty() {
+        prefix.push(',');
+    }
+    prefix += &serde_json::to_string(key)?;
+    prefix += ":\"";
 
-            request_builder
-                .body(stream)
-                .header(CONTENT_TYPE, "application/json")
+    let suffix = "\"}";
+
+    let total = prefix.len() as u64 + base64_len(file_size) + suffix.len() as u64;
+
+    debug!("json 请求体大小：{}", total);
+
+    let body = stream::iter([Ok(Bytes::from(prefix))])
+        .chain(base64_stream(file))
+        .chain(stream::iter([Ok(Bytes::from_static(suffix.as_bytes()))]));
+
+    let body = match window {
+        None => reqwest::Body::wrap_stream(body),
+        Some(w) => {
+            let window = Mutex::new(w.clone());
+
+            reqwest::Body::wrap_stream(ReadProgressStream::new(
+                body,
+                Box::new(move |_, progress| {
+                    let _ = window.lock().unwrap().emit(
+                        "upload://progress",
+                        ProgressPayload {
+                            id,
+                            progress,
+                            total,
+                        },
+                    );
+                }),
+            ))
         }
     };
 
-    let resp = builder.send().await?;
+    let resp = request_builder
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, total)
+        .body(body)
+        .send()
+        .await?;
 
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes as AxumBytes, http::HeaderMap, Router};
+    use reqwest::Method;
+
+    use super::*;
+    use crate::http::mock;
+
+    fn data(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn base64_stream_matches_encoding() {
+        for size in [
+            0,
+            1,
+            2,
+            3,
+            BASE64_CHUNK_SIZE - 1,
+            BASE64_CHUNK_SIZE,
+            BASE64_CHUNK_SIZE + 1,
+            BASE64_CHUNK_SIZE * 2 + 2,
+        ] {
+            let data = data(size);
+            let path = mock::temp_file("a.png", &data).await;
+
+            let chunks: Vec<Bytes> = base64_stream(File::open(&path).await.unwrap())
+                .map(|c| c.unwrap())
+                .collect()
+                .await;
+            let encoded = chunks.concat();
+
+            assert_eq!(
+                encoded,
+                general_purpose::STANDARD.encode(&data).as_bytes(),
+                "{size}"
+            );
+            assert_eq!(encoded.len() as u64, base64_len(size as u64), "{size}");
+        }
+    }
+
+    #[tokio::test]
+    async fn content_length_matches_body() {
+        let endpoint = mock::serve(Router::new().fallback(
+            |headers: HeaderMap, body: AxumBytes| async move {
+                let length: usize = headers[CONTENT_LENGTH.as_str()]
+                    .to_str()
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                assert_eq!(length, body.len());
+
+                let json: Value = serde_json::from_slice(&body).unwrap();
+                general_purpose::STANDARD
+                    .decode(json["image"].as_str().unwrap())
+                    .unwrap()
+            },
+        ))
+        .await;
+
+        let data = data(BASE64_CHUNK_SIZE + 5);
+        let path = mock::temp_file("a.png", &data).await;
+
+        let mut form = Map::new();
+        form.insert("name".to_owned(), Value::String("a.png".to_owned()));
+
+        let resp = upload(
+            reqwest::Client::new().request(Method::POST, endpoint),
+            None,
+            0,
+            form,
+            "image",
+            File::open(&path).await.unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(resp.status().is_success());
+        assert_eq!(resp.bytes().await.unwrap(), data);
+    }
+}
@@ -0,0 +1,37 @@
+//! 测试用的本地 http 服务
+
+use std::{net::Ipv4Addr, path::PathBuf};
+
+use axum::Router;
+use tokio::net::TcpListener;
+
+use crate::config::{Config, CONFIG};
+
+/// 在随机端口启动 router，返回 `http://127.0.0.1:port`
+pub async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    format!("http://{}", addr)
+}
+
+/// 没有配置文件时使用默认配置，上传时会读取压缩配置
+pub async fn init_config() {
+    let mut config = CONFIG.write().await;
+    if config.is_none() {
+        *config = Some(Config::default());
+    }
+}
+
+/// 在临时目录中写入一个文件，内容不需要是有效的图片
+pub async fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().simple().to_string());
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    let path = dir.join(name);
+    tokio::fs::write(&path, content).await.unwrap();
+
+    path
+}
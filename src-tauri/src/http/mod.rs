@@ -1,6 +1,11 @@
+use reqwest::{ClientBuilder, Proxy};
 use serde::Serialize;
 
+use crate::{config::ProxyKind, error::ProxyError, Up2bResult};
+
 pub mod json;
+#[cfg(test)]
+pub mod mock;
 pub mod multipart;
 
 #[derive(Clone, Serialize)]
@@ -9,3 +14,19 @@ struct ProgressPayload {
     progress: u64,
     total: u64,
 }
+
+/// 创建 ClientBuilder，所有图床的请求都应通过此方法创建客户端，以使代理配置生效
+pub fn client_builder(proxy: Option<&ProxyKind>) -> Up2bResult<ClientBuilder> {
+    let builder = ClientBuilder::new();
+
+    let builder = match proxy {
+        None => builder,
+        Some(p) => {
+            debug!("使用代理：{}", p.url());
+            let proxy = Proxy::all(p.url()).map_err(|e| ProxyError::Invalid(e.to_string()))?;
+            builder.proxy(proxy)
+        }
+    };
+
+    Ok(builder)
+}
@@ -5,7 +5,7 @@ use read_progress_stream::ReadProgressStream;
 use reqwest::{multipart::Part, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, WebviewWindow};
-use tokio::{fs::File, io::AsyncReadExt};
+use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 use crate::Up2bResult;
@@ -35,6 +35,7 @@ pub async fn file_to_body(
     )))
 }
 
+/// Buffer 用于不支持分块传输的图床，以流的形式发送但带有文件长度，不再将整个文件读入内存
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum FileKind {
@@ -42,14 +43,28 @@ pub enum FileKind {
     Buffer,
 }
 
+/// 表单中的文件字段
+#[derive(Clone, Copy)]
+pub struct FilePart<'r> {
+    pub name: &'r str,
+    pub kind: &'r FileKind,
+}
+
 pub struct UploadFile<'r> {
     file: File,
-    kind: &'r FileKind,
+    part: FilePart<'r>,
+    filename: &'r str,
+    mime_type: &'r str,
 }
 
 impl<'r> UploadFile<'r> {
-    pub fn new(file: File, kind: &'r FileKind) -> Self {
-        Self { file, kind }
+    pub fn new(file: File, part: FilePart<'r>, filename: &'r str, mime_type: &'r str) -> Self {
+        Self {
+            file,
+            part,
+            filename,
+            mime_type,
+        }
     }
 }
 
@@ -57,35 +72,32 @@ pub async fn upload(
     request_builder: RequestBuilder,
     window: Option<&WebviewWindow>,
     id: u32,
-    part_name: &str,
-    filename: &str,
-    mut upload_file: UploadFile<'_>,
-    mime_type: &str,
+    upload_file: UploadFile<'_>,
     texts: Option<&[(&str, &str)]>,
 ) -> Up2bResult<Response> {
-    let file_part = match &window {
-        None => Part::stream(upload_file.file),
-        Some(w) => match upload_file.kind {
-            FileKind::Buffer => {
-                let mut buf = Vec::new();
-                upload_file.file.read_to_end(&mut buf).await?;
-                Part::stream(buf)
-            }
-            FileKind::Stream => {
-                let body = file_to_body(
-                    id,
-                    Arc::new(Mutex::new(w.to_owned().clone())),
-                    upload_file.file,
-                )
-                .await?;
-                Part::stream(body)
-            }
-        },
+    let file_size = upload_file.file.metadata().await?.len();
+
+    let body = match &window {
+        None => reqwest::Body::from(upload_file.file),
+        Some(w) => {
+            file_to_body(
+                id,
+                Arc::new(Mutex::new(w.to_owned().clone())),
+                upload_file.file,
+            )
+            .await?
+        }
+    };
+
+    let file_part = match upload_file.part.kind {
+        FileKind::Buffer => Part::stream_with_length(body, file_size),
+        FileKind::Stream => Part::stream(body),
     }
-    .file_name(filename.to_owned())
-    .mime_str(mime_type)?;
+    .file_name(upload_file.filename.to_owned())
+    .mime_str(upload_file.mime_type)?;
 
-    let mut form = reqwest::multipart::Form::new().part(part_name.to_owned(), file_part);
+    let mut form =
+        reqwest::multipart::Form::new().part(upload_file.part.name.to_owned(), file_part);
 
     if let Some(text_parts) = texts {
         for (name, value) in text_parts.iter() {
@@ -2,7 +2,9 @@ use std::{collections::HashMap, env};
 
 use log::{Level, LevelFilter};
 use simplelog::{Color, Config, ConfigBuilder};
-use time::macros::{format_description, offset};
+use time::macros::format_description;
+
+use crate::util::time::LOCAL_OFFSET;
 
 pub fn logger_config(is_term: bool) -> Config {
     let mut config = &mut ConfigBuilder::new();
@@ -23,9 +25,7 @@ pub fn logger_config(is_term: bool) -> Config {
         ));
     }
 
-    // time::OffsetDateTime::now_local() 方法在 POSIX 上线程不安全：https://github.com/time-rs/time/issues/457
-    // 直接指定时区为 +8。
-    config.set_time_offset(offset!(+8));
+    config.set_time_offset(LOCAL_OFFSET);
 
     config
         .set_location_level(LevelFilter::Error)
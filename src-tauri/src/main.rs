@@ -2,12 +2,18 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod config;
+mod document;
 mod error;
+mod history;
 mod http;
 mod logger;
 mod manager;
+mod migrate;
+mod server;
 mod setup;
+mod upload;
 mod util;
+mod watch;
 
 #[macro_use]
 extern crate log;
@@ -15,19 +21,28 @@ extern crate log;
 extern crate lazy_static;
 extern crate simplelog;
 
+use document::{RewriteOptions, RewriteReport};
 use error::AuthConfigError;
+use history::{HistoryItem, HistoryQuery};
 use manager::api::Api;
 use manager::smms::SMMS_API;
 use manager::ManagerItem;
+use migrate::{MigrateOptions, MigrateReport};
+use server::ServerConfig;
 use simplelog::CombinedLogger;
 #[cfg(not(debug_assertions))]
 use simplelog::WriteLogger;
 #[cfg(debug_assertions)]
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use tauri::WebviewWindow;
+use tauri::{AppHandle, WebviewWindow};
+use upload::MirrorUploadResult;
+use util::template::{find_link_template, link_templates, render, LinkContext, LinkTemplate};
 
-use crate::config::{write_config, Config, ManagerAuthConfigKind, APP_CONFIG_DIR, CONFIG};
+use crate::config::{
+    write_config, Config, ManagerAuthConfigKind, ProxyKind, APP_CONFIG_DIR, CONFIG,
+};
 use crate::logger::{log_level, logger_config};
 use crate::manager::{
     use_manager, AllowedImageFormat, DeleteResponse, Extra, ImageItem, Manage, UploadResult,
@@ -37,20 +52,47 @@ use crate::manager::{
 pub use crate::error::{ConfigError, Up2bError, Up2bResult};
 use crate::manager::ManagerCode;
 
-async fn using_manager() -> Up2bResult<Box<dyn Manage>> {
+/// 根据配置创建指定图床的管理器，不要求是正在使用的图床
+async fn get_manager(code: &ManagerCode) -> Up2bResult<Box<dyn Manage>> {
     let config = match CONFIG.read().await.as_ref() {
         None => return Err(Up2bError::Config(ConfigError::NotFound)),
         Some(c) => c.clone(),
     };
 
-    let using = config.using();
-    let auth_config = config.get_auth_config(&using);
+    let auth_config = config.get_auth_config(code);
 
     if let Some(c) = auth_config {
-        return use_manager(&using, c);
+        let manager = use_manager(code, c, config.active_proxy())?;
+
+        #[cfg(feature = "compress")]
+        let manager = {
+            let mut manager = manager;
+            if let Some(f) = config.compressed_format(code) {
+                manager.set_compressed_format(f.clone());
+            }
+            manager
+        };
+
+        return Ok(manager);
     }
 
-    Err(Up2bError::AuthConfig(AuthConfigError::Null(using.clone())))
+    Err(Up2bError::AuthConfig(AuthConfigError::Null(code.clone())))
+}
+
+/// 正在使用的图床及其管理器
+async fn using_uploader() -> Up2bResult<(ManagerCode, Box<dyn Manage>)> {
+    let using = match CONFIG.read().await.as_ref() {
+        None => return Err(Up2bError::Config(ConfigError::NotFound)),
+        Some(c) => c.using().clone(),
+    };
+
+    let uploader = get_manager(&using).await?;
+
+    Ok((using, uploader))
+}
+
+async fn using_manager() -> Up2bResult<Box<dyn Manage>> {
+    Ok(using_uploader().await?.1)
 }
 
 #[tauri::command]
@@ -64,7 +106,7 @@ async fn get_managers() -> Vec<ManagerItem> {
         let auth_config = c.auth_config();
         for key in auth_config.keys() {
             if let ManagerCode::Custom(_) = key {
-                managers.push(key.clone().to_manager_item());
+                managers.push(key.clone().into_manager_item());
             }
         }
     }
@@ -72,30 +114,189 @@ async fn get_managers() -> Vec<ManagerItem> {
     managers
 }
 
+async fn upload_concurrency() -> usize {
+    match CONFIG.read().await.as_ref() {
+        None => Config::default().upload_concurrency(),
+        Some(c) => c.upload_concurrency(),
+    }
+}
+
 #[tauri::command]
 async fn get_all_images() -> Up2bResult<Vec<ImageItem>> {
     trace!("获取图片列表");
 
-    let uploader = using_manager().await?;
+    let (manager, uploader) = using_uploader().await?;
+
+    let mut images = uploader.get_all_images().await?;
+
+    // 使用上传时生成的缩略图，并隐藏作为缩略图上传的图片
+    match history::thumbnails(&manager).await {
+        Ok(thumbs) => {
+            let thumb_urls: HashSet<&String> = thumbs.values().collect();
+            images.retain(|i| !thumb_urls.contains(&i.url));
 
-    Ok(uploader.get_all_images().await?)
+            for image in images.iter_mut().filter(|i| i.thumb().is_none()) {
+                if let Some(t) = thumbs.get(&image.url) {
+                    image.set_thumb(t.clone());
+                }
+            }
+        }
+        Err(e) => warn!("读取上传历史失败：{}", e),
+    }
+
+    Ok(images)
 }
 
 #[tauri::command]
 async fn delete_image(delete_id: String) -> Up2bResult<DeleteResponse> {
     trace!("删除图片：{}", delete_id);
-    let uploader = using_manager().await?;
+    let (manager, uploader) = using_uploader().await?;
+
+    let response = uploader.delete_image(&delete_id).await?;
+
+    if response.success() {
+        match history::remove_by_deleted_id(&manager, &delete_id).await {
+            Ok(items) => {
+                for item in items {
+                    upload::remove_thumbnail(Some(uploader.as_ref()), &item).await;
+                }
+            }
+            Err(e) => warn!("删除上传历史失败：{}", e),
+        }
+    }
 
-    Ok(uploader.delete_image(&delete_id).await?)
+    Ok(response)
 }
 
+/// force 为 true 时即使相同内容的图片已上传过也重新上传，下同
 #[tauri::command]
-async fn upload_image(window: WebviewWindow, image_path: PathBuf) -> Up2bResult<UploadResult> {
+async fn upload_image(
+    window: WebviewWindow,
+    image_path: PathBuf,
+    force: Option<bool>,
+) -> Up2bResult<UploadResult> {
     trace!("上传图片 {image_path:?}");
 
-    let uploader = using_manager().await?;
+    let (manager, uploader) = using_uploader().await?;
+
+    Ok(upload::upload_image(
+        uploader.as_ref(),
+        &manager,
+        Some(window),
+        1,
+        &image_path,
+        force.unwrap_or_default(),
+    )
+    .await)
+}
 
-    Ok(uploader.upload_image(Some(window), 1, &image_path).await)
+/// 上传图片数据，比如剪贴板中的截图
+#[tauri::command]
+async fn upload_image_data(
+    window: WebviewWindow,
+    data: Vec<u8>,
+    filename: Option<String>,
+    force: Option<bool>,
+) -> Up2bResult<UploadResult> {
+    trace!("上传图片数据：filename={:?}, size={}", filename, data.len());
+
+    let (manager, uploader) = using_uploader().await?;
+
+    let image_path = upload::save_image_data(&data, filename.as_deref()).await?;
+
+    let result = upload::upload_image(
+        uploader.as_ref(),
+        &manager,
+        Some(window),
+        1,
+        &image_path,
+        force.unwrap_or_default(),
+    )
+    .await;
+
+    util::image::remove_temp_image(&image_path).await;
+
+    Ok(result)
+}
+
+/// 下载远程图片并上传到当前图床
+#[tauri::command]
+async fn upload_image_url(
+    window: WebviewWindow,
+    url: String,
+    force: Option<bool>,
+) -> Up2bResult<UploadResult> {
+    trace!("上传远程图片 {url}");
+
+    let (manager, uploader) = using_uploader().await?;
+
+    let image_path = upload::download_image(&url).await?;
+
+    let result = upload::upload_image(
+        uploader.as_ref(),
+        &manager,
+        Some(window),
+        1,
+        &image_path,
+        force.unwrap_or_default(),
+    )
+    .await;
+
+    util::image::remove_temp_image(&image_path).await;
+
+    Ok(result)
+}
+
+/// 同时上传到正在使用的图床和配置中的镜像图床
+#[tauri::command]
+async fn upload_image_mirror(
+    window: WebviewWindow,
+    image_path: PathBuf,
+    force: Option<bool>,
+) -> Up2bResult<MirrorUploadResult> {
+    trace!("镜像上传图片 {image_path:?}");
+
+    let managers = match CONFIG.read().await.as_ref() {
+        None => return Err(Up2bError::Config(ConfigError::NotFound)),
+        Some(c) => c.mirror_set(),
+    };
+
+    Ok(upload::upload_image_mirror(
+        &managers,
+        Some(window),
+        1,
+        &image_path,
+        force.unwrap_or_default(),
+    )
+    .await)
+}
+
+/// concurrency 为 None 时使用配置中的并发数
+#[tauri::command]
+async fn upload_images(
+    window: WebviewWindow,
+    image_paths: Vec<PathBuf>,
+    concurrency: Option<usize>,
+    force: Option<bool>,
+) -> Up2bResult<Vec<UploadResult>> {
+    trace!("批量上传图片 {image_paths:?}");
+
+    let concurrency = match concurrency {
+        Some(c) => c,
+        None => upload_concurrency().await,
+    };
+
+    let (manager, uploader) = using_uploader().await?;
+
+    Ok(upload::upload_images(
+        uploader.as_ref(),
+        &manager,
+        Some(window),
+        &image_paths,
+        concurrency,
+        force.unwrap_or_default(),
+    )
+    .await)
 }
 
 #[tauri::command]
@@ -103,7 +304,13 @@ async fn verify(
     image_bed: ManagerCode,
     config: ManagerAuthConfigKind,
 ) -> Up2bResult<Option<Extra>> {
-    let uploader = use_manager(&image_bed, &config)?;
+    let proxy = CONFIG
+        .read()
+        .await
+        .as_ref()
+        .and_then(|c| c.active_proxy().cloned());
+
+    let uploader = use_manager(&image_bed, &config, proxy.as_ref())?;
 
     uploader.verify().await
 }
@@ -180,6 +387,215 @@ async fn automatic_compression() -> bool {
         .automatic_compression()
 }
 
+#[tauri::command]
+async fn get_proxy() -> Option<ProxyKind> {
+    CONFIG
+        .read()
+        .await
+        .as_ref()
+        .and_then(|c| c.proxy().cloned())
+}
+
+#[tauri::command]
+async fn set_proxy(proxy: ProxyKind) -> Up2bResult<()> {
+    let mut write_guard = CONFIG.write().await;
+
+    let mut config = write_guard.take().unwrap_or_default();
+
+    let result = config.set_proxy(proxy);
+    if result.is_ok() {
+        write_config(&config)?;
+    }
+
+    *write_guard = Some(config);
+
+    result
+}
+
+#[tauri::command]
+async fn enable_proxy() -> Up2bResult<()> {
+    let mut write_guard = CONFIG.write().await;
+
+    let mut config = match write_guard.take() {
+        None => return Err(Up2bError::Config(ConfigError::NotFound)),
+        Some(c) => c,
+    };
+
+    let result = config.enable_proxy();
+    if result.is_ok() {
+        write_config(&config)?;
+    }
+
+    *write_guard = Some(config);
+
+    result
+}
+
+#[tauri::command]
+async fn disable_proxy() -> Up2bResult<()> {
+    let mut write_guard = CONFIG.write().await;
+
+    if let Some(mut config) = write_guard.take() {
+        config.disable_proxy();
+        write_config(&config)?;
+        *write_guard = Some(config);
+    }
+
+    Ok(())
+}
+
+/// 查询上传历史，不依赖图床的图片列表接口
+#[tauri::command]
+async fn get_upload_history(query: Option<HistoryQuery>) -> Up2bResult<Vec<HistoryItem>> {
+    history::query(query.unwrap_or_default()).await
+}
+
+/// 删除上传历史，delete_remote 为 true 时同时从图床中删除图片和上传的缩略图，
+/// 图床删除失败时保留记录。本地缩略图总会被删除
+#[tauri::command]
+async fn delete_upload_history(id: u64, delete_remote: bool) -> Up2bResult<DeleteResponse> {
+    trace!("删除上传历史：id={}, delete_remote={}", id, delete_remote);
+
+    let item = match history::get(id).await? {
+        Some(i) => i,
+        None => return Err(Up2bError::Other(format!("上传历史不存在：{}", id))),
+    };
+
+    let manager = match delete_remote {
+        true => Some(get_manager(item.manager()).await?),
+        false => None,
+    };
+
+    let response = match &manager {
+        Some(m) => m.delete_image(item.deleted_id()).await?,
+        None => DeleteResponse::ok(),
+    };
+
+    if response.success() {
+        if let Some(item) = history::remove(id).await? {
+            upload::remove_thumbnail(manager.as_deref(), &item).await;
+        }
+    }
+
+    Ok(response)
+}
+
+/// 清空上传历史，manager 为 None 时清空所有图床的记录
+#[tauri::command]
+async fn clear_upload_history(manager: Option<ManagerCode>) -> Up2bResult<()> {
+    history::clear(manager).await
+}
+
+/// 上传 Markdown 或 HTML 文档中的本地图片，并替换为上传后的链接
+#[tauri::command]
+async fn rewrite_document(
+    window: WebviewWindow,
+    options: RewriteOptions,
+) -> Up2bResult<RewriteReport> {
+    trace!("替换文档中的本地图片：{:?}", options);
+
+    document::rewrite_document(options, Some(window)).await
+}
+
+/// 开始监视目录并自动上传新图片，dirs 为 None 时使用配置中的目录
+#[tauri::command]
+async fn start_watching(app: AppHandle, dirs: Option<Vec<PathBuf>>) -> Up2bResult<()> {
+    let dirs = match dirs {
+        Some(d) => d,
+        None => match CONFIG.read().await.as_ref() {
+            None => return Err(Up2bError::Config(ConfigError::NotFound)),
+            Some(c) => c.watch_dirs().to_vec(),
+        },
+    };
+
+    if dirs.is_empty() {
+        return Err(Up2bError::Config(ConfigError::IsNull(
+            "watch_dirs".to_owned(),
+        )));
+    }
+
+    watch::start(app, dirs)
+}
+
+#[tauri::command]
+fn stop_watching() {
+    watch::stop();
+}
+
+#[tauri::command]
+fn is_watching() -> bool {
+    watch::is_watching()
+}
+
+/// 启动兼容 PicGo 的本地上传服务，config 为 None 时使用配置中的服务配置，
+/// 没有 token 时使用配置中的 token
+#[tauri::command]
+async fn start_server(config: Option<ServerConfig>) -> Up2bResult<()> {
+    let saved = server::load_config().await?;
+
+    let config = match config {
+        Some(c) if c.token.is_empty() => ServerConfig {
+            token: saved.token,
+            ..c
+        },
+        Some(c) => c,
+        None => saved,
+    };
+
+    server::start(config).await
+}
+
+#[tauri::command]
+fn stop_server() {
+    server::stop();
+}
+
+#[tauri::command]
+fn is_serving() -> bool {
+    server::is_serving()
+}
+
+/// 将一个图床中的所有图片迁移到另一个图床
+#[tauri::command]
+async fn migrate_images(
+    window: WebviewWindow,
+    options: MigrateOptions,
+) -> Up2bResult<MigrateReport> {
+    trace!("迁移图片：{:?}", options);
+
+    migrate::migrate(options, Some(window)).await
+}
+
+/// 获取所有链接模板，包括内置模板和自定义模板
+#[tauri::command]
+async fn get_link_templates() -> Vec<LinkTemplate> {
+    match CONFIG.read().await.as_ref() {
+        None => link_templates(&HashMap::new()),
+        Some(c) => link_templates(c.link_templates()),
+    }
+}
+
+/// 用指定的模板生成图片链接，template 为模板名，默认为 url
+#[tauri::command]
+async fn render_link(
+    image: ImageItem,
+    template: Option<String>,
+    image_path: Option<PathBuf>,
+    alt: Option<String>,
+) -> Up2bResult<String> {
+    let name = template.as_deref().unwrap_or("url");
+
+    let template = match CONFIG.read().await.as_ref() {
+        None => find_link_template(&HashMap::new(), name),
+        Some(c) => find_link_template(c.link_templates(), name),
+    }
+    .ok_or_else(|| Up2bError::Other(format!("链接模板不存在：{}", name)))?;
+
+    let context = LinkContext::new(&image, image_path.as_deref(), alt.as_deref());
+
+    Ok(render(&template, &context))
+}
+
 #[tauri::command]
 async fn check_new_manager_code(manager_code: ManagerCode) -> bool {
     let conf = CONFIG.read().await;
@@ -239,9 +655,8 @@ async fn main() {
 
     info!("配置文件路径：{:?}", *APP_CONFIG_DIR);
 
-    #[cfg(feature = "compress")]
     {
-        use crate::util::image::compress::TEMP_DIR;
+        use crate::util::image::TEMP_DIR;
         use std::fs::create_dir;
 
         if !TEMP_DIR.exists() {
@@ -254,8 +669,11 @@ async fn main() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_cli::init())
-        .setup(|app| match setup::setup(&app) {
-            Err(_) => Ok(app.handle().exit(1)),
+        .setup(|app| match setup::setup(app) {
+            Err(_) => {
+                app.handle().exit(1);
+                Ok(())
+            }
             _ => Ok(()),
         });
 
@@ -264,6 +682,9 @@ async fn main() {
             get_all_images,
             delete_image,
             upload_image,
+            upload_images,
+            upload_image_data,
+            upload_image_url,
             get_config,
             update_config,
             compress_state,
@@ -277,6 +698,24 @@ async fn main() {
             get_managers,
             check_new_manager_code,
             new_custom_manager,
+            get_proxy,
+            set_proxy,
+            enable_proxy,
+            disable_proxy,
+            get_link_templates,
+            render_link,
+            get_upload_history,
+            delete_upload_history,
+            clear_upload_history,
+            upload_image_mirror,
+            migrate_images,
+            rewrite_document,
+            start_watching,
+            stop_watching,
+            is_watching,
+            start_server,
+            stop_server,
+            is_serving,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -56,7 +56,7 @@ impl DeleteResponseController {
             } => {
                 let json: Value = response.json().await?;
 
-                let value = json.get_value_by_keys(&key);
+                let value = json.get_value_by_keys(key);
 
                 if value.is_null() {
                     return Err(Up2bError::Other("无法获取删除状态值".to_owned()));
@@ -81,6 +81,7 @@ impl ListResponseController {
                     url,
                     deleted_id,
                     thumb: None,
+                    manager: None,
                 },
                 Some(k) => {
                     let thumb = match item.get_value_by_keys(k) {
@@ -94,6 +95,7 @@ impl ListResponseController {
                         url,
                         deleted_id,
                         thumb,
+                        manager: None,
                     }
                 }
             };
@@ -1,6 +1,7 @@
 pub(crate) mod delete;
 pub(crate) mod list;
 pub(crate) mod upload;
+pub(crate) mod verify;
 
 use std::{path::Path, str::FromStr};
 
@@ -14,7 +15,10 @@ use serde_json::{Map, Value};
 use tauri::WebviewWindow;
 
 use crate::{
-    error::HeaderError, http::multipart::FileKind, manager::RequestWithBodyMethod, Up2bResult,
+    error::HeaderError,
+    http::multipart::{FileKind, FilePart},
+    manager::{RequestWithBodyMethod, UploadRequest},
+    Up2bResult,
 };
 
 pub(crate) use self::delete::Delete;
@@ -24,6 +28,9 @@ pub(crate) use self::upload::{
     Upload, UploadContentType, UploadResponseController, UploadResponseErrorController,
     UploadResponseStatus, UploadResponseSuccuessController,
 };
+pub(crate) use self::verify::{
+    Verify, VerifyRequestMethod, VerifyResponseController, VerifyResponseStatus,
+};
 
 #[cfg(feature = "compress")]
 use super::CompressedFormat;
@@ -53,15 +60,19 @@ pub struct Api {
     upload: Upload,
     list: List,
     delete: Delete,
+    /// 验证 token 的接口，可选
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verify: Option<Verify>,
 }
 
 impl Api {
-    pub fn new<S: Into<String>>(
+    pub fn new<S: Into<String>, V: Into<Option<Verify>>>(
         base_url: S,
         auth_method: AuthMethod,
         upload: Upload,
         list: List,
         delete: Delete,
+        verify: V,
     ) -> Self {
         Self {
             base_url: base_url.into(),
@@ -69,6 +80,7 @@ impl Api {
             upload,
             list,
             delete,
+            verify: verify.into(),
         }
     }
 
@@ -159,6 +171,33 @@ impl BaseApiManager {
         self.api.list.controller.parse(response).await
     }
 
+    /// 未配置验证接口时不验证
+    pub async fn verify(&self) -> Up2bResult<Option<Extra>> {
+        let verify = match &self.api.verify {
+            None => return Ok(None),
+            Some(v) => v,
+        };
+
+        let url = self.inner.url(&verify.path);
+
+        let response = match &verify.method {
+            VerifyRequestMethod::Get => self.inner.get(&url, self.headers()?).await?,
+            VerifyRequestMethod::Post { body } => {
+                let mut body = body.clone();
+
+                if let AuthMethod::Body { key } = &self.api.auth_method {
+                    body.insert(key.to_owned(), Value::String(self.token.clone()));
+                }
+
+                self.inner
+                    .json(RequestWithBodyMethod::Post, &url, self.headers()?, body)
+                    .await?
+            }
+        };
+
+        verify.controller.parse(response).await
+    }
+
     async fn delete_by_delete(&self, kind: &DeleteKeyKind, id: &str) -> Up2bResult<Response> {
         // DELETE 删除认证方式只能是 headers
         let url = match kind {
@@ -201,7 +240,7 @@ impl BaseApiManager {
 
         self.inner
             .json(
-                RequestWithBodyMethod::POST,
+                RequestWithBodyMethod::Post,
                 &self.inner.url(&self.api.delete.path),
                 self.headers()?,
                 body,
@@ -235,10 +274,12 @@ impl BaseApiManager {
                 self.inner
                     .upload_json(
                         window,
-                        RequestWithBodyMethod::POST,
                         id,
-                        &self.inner.url(&self.api.upload.path),
-                        headers,
+                        UploadRequest {
+                            method: RequestWithBodyMethod::Post,
+                            url: &self.inner.url(&self.api.upload.path),
+                            headers,
+                        },
                         key,
                         image_path,
                         form,
@@ -253,11 +294,16 @@ impl BaseApiManager {
                     .upload_multipart(
                         window,
                         id,
-                        &self.inner.url(&self.api.upload.path),
-                        headers,
+                        UploadRequest {
+                            method: RequestWithBodyMethod::Post,
+                            url: &self.inner.url(&self.api.upload.path),
+                            headers,
+                        },
                         image_path,
-                        &file_part_name,
-                        file_kind,
+                        FilePart {
+                            name: file_part_name,
+                            kind: file_kind,
+                        },
                         form,
                     )
                     .await?
@@ -278,19 +324,22 @@ impl Manage for BaseApiManager {
         self.api.upload.allowed_formats.clone()
     }
 
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.compressed_format = format;
+    }
+
     fn support_stream(&self) -> bool {
         match &self.api.upload.content_type {
             UploadContentType::Json { .. } => true,
-            UploadContentType::Multipart { file_kind, .. } => match file_kind {
-                FileKind::Stream => true,
-                _ => false,
-            },
+            UploadContentType::Multipart { file_kind, .. } => {
+                matches!(file_kind, FileKind::Stream)
+            }
         }
     }
 
     async fn verify(&self) -> Up2bResult<Option<Extra>> {
-        // TODO: api 类型的图床的 token 验证以后再实现
-        Ok(None)
+        BaseApiManager::verify(self).await
     }
 
     async fn get_all_images(&self) -> Up2bResult<Vec<ImageItem>> {
@@ -343,3 +392,54 @@ impl SerdeValueParser for Value {
         current_value.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes, http::StatusCode, Router};
+
+    use super::*;
+    use crate::{error::Up2bError, http::mock, manager::smms::SMMS_API};
+
+    async fn upload_with_status(status: u16, body: &'static str) -> Up2bError {
+        mock::init_config().await;
+
+        let status = StatusCode::from_u16(status).unwrap();
+        let base_url =
+            mock::serve(Router::new().fallback(move |_: Bytes| async move { (status, body) }))
+                .await;
+
+        let api = SMMS_API.clone();
+
+        let inner = BaseManager::new(
+            "smms",
+            &base_url,
+            api.max_size(),
+            api.allowed_formats().to_vec(),
+            Some(api.timeout()),
+            #[cfg(feature = "compress")]
+            api.compressed_format().clone(),
+            None,
+        )
+        .unwrap();
+        let manager = BaseApiManager::new(inner, "token", &api);
+
+        let image_path = mock::temp_file("a.png", b"png").await;
+        manager
+            .upload(None, 0, &image_path, None)
+            .await
+            .err()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn server_error_is_retryable() {
+        let e = upload_with_status(502, "<html>Bad Gateway</html>").await;
+        assert!(e.is_retryable(), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn client_error_is_not_retryable() {
+        let e = upload_with_status(400, r#"{"success": false, "message": "Invalid token"}"#).await;
+        assert!(!e.is_retryable(), "{e:?}");
+    }
+}
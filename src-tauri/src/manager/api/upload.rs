@@ -6,7 +6,7 @@ use serde_json::{Map, Value};
 use crate::{
     error::UploadError,
     http::multipart::FileKind,
-    manager::{AllowedImageFormat, ImageItem},
+    manager::{check_server_error, AllowedImageFormat, ImageItem},
     Up2bError, Up2bResult,
 };
 
@@ -54,7 +54,7 @@ impl UploadResponseErrorController {
         match &self.repeated_regex {
             Some(r) => {
                 // regex 无法序列化，只能每次使用时初始化
-                let regex = match Regex::new(&r) {
+                let regex = match Regex::new(r) {
                     Ok(r) => r,
                     Err(e) => return e.into(),
                 };
@@ -113,6 +113,7 @@ impl UploadResponseSuccuessController {
                 url,
                 deleted_id,
                 thumb: None,
+                manager: None,
             }),
             Some(k) => {
                 let thumb = match json.get_value_by_keys(k) {
@@ -125,6 +126,7 @@ impl UploadResponseSuccuessController {
                     url,
                     deleted_id,
                     thumb,
+                    manager: None,
                 })
             }
         }
@@ -167,6 +169,8 @@ impl UploadResponseController {
     }
 
     pub async fn parse(&self, response: Response) -> Up2bResult<ImageItem> {
+        check_server_error(response.status())?;
+
         let json: Value = response.json().await?;
 
         debug!("响应体：{}", json);
@@ -199,13 +203,13 @@ pub struct Upload {
 }
 
 impl Upload {
-    pub fn new<T: Into<Option<u8>>, M: Into<Option<Map<String, Value>>>>(
+    /// 请求体中图片之外的其他部分默认为空
+    pub fn new<T: Into<Option<u8>>>(
         url: &str,
         max_size: u8,
         allowed_formats: Vec<AllowedImageFormat>,
         #[cfg(feature = "compress")] compressed_format: CompressedFormat,
         content_type: UploadContentType,
-        other_body: M,
         controller: UploadResponseController,
         timeout: T,
     ) -> Self {
@@ -227,7 +231,7 @@ impl Upload {
             compressed_format,
             content_type,
             controller,
-            other_body: other_body.into(),
+            other_body: None,
             timeout,
         }
     }
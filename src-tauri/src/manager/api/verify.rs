@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    error::TokenError,
+    manager::{api::SerdeValueParser, Extra},
+    Up2bError, Up2bResult,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "UPPERCASE")]
+pub enum VerifyRequestMethod {
+    Get,
+    Post { body: Map<String, Value> },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerifyResponseStatus {
+    key: String,
+    value: Value,
+}
+
+impl VerifyResponseStatus {
+    pub fn new<S: Into<String>, V: Into<Value>>(key: S, value: V) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerifyResponseController {
+    /// 为 None 时只通过状态码判断 token 是否有效
+    status: Option<VerifyResponseStatus>,
+    /// 失败时的消息 key
+    message_key: Option<String>,
+    /// 验证成功后返回给前端的额外信息，键为信息名，值为响应体中的 key
+    extra: Option<HashMap<String, String>>,
+}
+
+impl VerifyResponseController {
+    pub fn new<S: Into<Option<VerifyResponseStatus>>, O: Into<Option<String>>>(
+        status: S,
+        message_key: O,
+        extra: Option<HashMap<String, String>>,
+    ) -> Self {
+        Self {
+            status: status.into(),
+            message_key: message_key.into(),
+            extra,
+        }
+    }
+
+    fn message(&self, json: &Value) -> String {
+        match &self.message_key {
+            None => "unkown".to_owned(),
+            Some(k) => match json.get_value_by_keys(k) {
+                Value::String(s) => s,
+                Value::Null => "unkown".to_owned(),
+                v => v.to_string(),
+            },
+        }
+    }
+
+    pub(super) async fn parse(&self, response: Response) -> Up2bResult<Option<Extra>> {
+        let status = response.status();
+
+        debug!("验证 token 的响应状态码：{}", status);
+
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            let message = match response.json::<Value>().await {
+                Ok(json) => self.message(&json),
+                Err(_) => status.to_string(),
+            };
+
+            return Err(TokenError::Invalid(message).into());
+        }
+
+        if !status.is_success() {
+            return Err(Up2bError::Status(status));
+        }
+
+        let json: Value = response.json().await?;
+
+        debug!("验证 token 的响应体：{}", json);
+
+        if let Some(s) = &self.status {
+            if json.get_value_by_keys(&s.key) != s.value {
+                return Err(TokenError::Invalid(self.message(&json)).into());
+            }
+        }
+
+        let extra = match &self.extra {
+            None => return Ok(None),
+            Some(e) => e,
+        };
+
+        let mut result = Extra::with_capacity(extra.len());
+
+        for (name, key) in extra.iter() {
+            match json.get_value_by_keys(key) {
+                Value::Null => {}
+                Value::String(s) => {
+                    result.insert(name.to_owned(), s);
+                }
+                v => {
+                    result.insert(name.to_owned(), v.to_string());
+                }
+            }
+        }
+
+        Ok(Some(result))
+    }
+}
+
+/// 验证 token 的接口，应是一个需要认证的轻量接口，比如获取用户信息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Verify {
+    pub(super) path: String,
+    pub(super) method: VerifyRequestMethod,
+    pub(super) controller: VerifyResponseController,
+}
+
+impl Verify {
+    pub fn new(
+        url: &str,
+        method: VerifyRequestMethod,
+        controller: VerifyResponseController,
+    ) -> Self {
+        Self {
+            path: url.to_owned(),
+            method,
+            controller,
+        }
+    }
+}
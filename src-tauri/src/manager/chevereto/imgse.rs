@@ -4,15 +4,17 @@ use std::path::Path;
 use async_trait::async_trait;
 use tauri::WebviewWindow;
 
+use crate::config::ProxyKind;
 use crate::http::multipart::FileKind;
+#[cfg(feature = "compress")]
+use crate::manager::CompressedFormat;
 use crate::manager::{
-    AllowedImageFormat, DeleteResponse, Extra, ImageItem, Manage, ManagerCode, UploadResult,
+    AllowedImageFormat, BaseManager, DeleteResponse, Extra, ImageItem, Manage, ManagerCode,
+    UploadResult,
 };
 use crate::Up2bResult;
 
 use super::Chevereto;
-#[cfg(feature = "compress")]
-use super::CompressedFormat;
 
 #[derive(Debug)]
 pub struct Imgse {
@@ -25,27 +27,33 @@ impl Imgse {
         password: S,
         timeout: Option<u8>,
         extra: Option<&HashMap<String, String>>,
-    ) -> Self {
-        Self {
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let manager = BaseManager::new(
+            "imgse.com",
+            "https://imgse.com",
+            10,
+            vec![
+                AllowedImageFormat::Jpeg,
+                AllowedImageFormat::Png,
+                AllowedImageFormat::Gif,
+            ],
+            Some(timeout.unwrap_or(60)),
+            #[cfg(feature = "compress")]
+            CompressedFormat::Jpeg,
+            proxy,
+        )?;
+
+        Ok(Self {
             inner: Chevereto::new(
                 ManagerCode::Imgse,
-                "imgse.com",
-                "https://imgse.com",
+                manager,
+                FileKind::Buffer,
                 username,
                 password,
-                10,
-                FileKind::Buffer,
-                vec![
-                    AllowedImageFormat::Jpeg,
-                    AllowedImageFormat::Png,
-                    AllowedImageFormat::Gif,
-                ],
-                timeout.unwrap_or(60),
                 extra,
-                #[cfg(feature = "compress")]
-                CompressedFormat::JPEG,
             ),
-        }
+        })
     }
 }
 
@@ -55,6 +63,11 @@ impl Manage for Imgse {
         self.inner.inner.allowed_formats.to_owned()
     }
 
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.inner.compressed_format = format;
+    }
+
     fn support_stream(&self) -> bool {
         self.inner.file_kind == FileKind::Stream
     }
@@ -95,6 +108,7 @@ impl Manage for Imgse {
             url: resp.image.url,
             deleted_id: resp.image.name,
             thumb: Some(resp.image.thumb.url),
+            manager: None,
         })
     }
 }
@@ -4,15 +4,17 @@ use std::path::Path;
 use async_trait::async_trait;
 use tauri::WebviewWindow;
 
+use crate::config::ProxyKind;
 use crate::http::multipart::FileKind;
+#[cfg(feature = "compress")]
+use crate::manager::CompressedFormat;
 use crate::manager::{
-    AllowedImageFormat, DeleteResponse, Extra, ImageItem, Manage, ManagerCode, UploadResult,
+    AllowedImageFormat, BaseManager, DeleteResponse, Extra, ImageItem, Manage, ManagerCode,
+    UploadResult,
 };
 use crate::Up2bResult;
 
 use super::Chevereto;
-#[cfg(feature = "compress")]
-use super::CompressedFormat;
 
 #[derive(Debug)]
 pub struct Imgtg {
@@ -25,29 +27,35 @@ impl Imgtg {
         password: S,
         timeout: Option<u8>,
         extra: Option<&HashMap<String, String>>,
-    ) -> Self {
-        Self {
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let manager = BaseManager::new(
+            "img.tg",
+            "https://img.tg",
+            5,
+            vec![
+                AllowedImageFormat::Jpeg,
+                AllowedImageFormat::Png,
+                AllowedImageFormat::Bmp,
+                AllowedImageFormat::Gif,
+                AllowedImageFormat::Webp,
+            ],
+            Some(timeout.unwrap_or(60)),
+            #[cfg(feature = "compress")]
+            CompressedFormat::Webp,
+            proxy,
+        )?;
+
+        Ok(Self {
             inner: Chevereto::new(
                 ManagerCode::Imgtg,
-                "img.tg",
-                "https://img.tg",
+                manager,
+                FileKind::Stream,
                 username,
                 password,
-                5,
-                FileKind::Stream,
-                vec![
-                    AllowedImageFormat::Jpeg,
-                    AllowedImageFormat::Png,
-                    AllowedImageFormat::Bmp,
-                    AllowedImageFormat::Gif,
-                    AllowedImageFormat::Webp,
-                ],
-                timeout.unwrap_or(60),
                 extra,
-                #[cfg(feature = "compress")]
-                CompressedFormat::WEBP,
             ),
-        }
+        })
     }
 }
 
@@ -57,6 +65,11 @@ impl Manage for Imgtg {
         self.inner.inner.allowed_formats.clone()
     }
 
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.inner.compressed_format = format;
+    }
+
     fn support_stream(&self) -> bool {
         self.inner.file_kind == FileKind::Stream
     }
@@ -97,6 +110,7 @@ impl Manage for Imgtg {
             url: resp.image.url,
             deleted_id: resp.image.name,
             thumb: Some(resp.image.thumb.url),
+            manager: None,
         })
     }
 }
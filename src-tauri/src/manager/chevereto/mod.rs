@@ -12,50 +12,25 @@ use tauri::WebviewWindow;
 use crate::{
     config::{write_config, ManagerAuthConfigKind, CONFIG},
     error::{CheveretoError, Up2bResult},
-    http::multipart::FileKind,
+    http::{
+        client_builder,
+        multipart::{FileKind, FilePart},
+    },
     manager::DeleteError,
     util::time::now,
     Up2bError,
 };
 
-#[cfg(feature = "compress")]
-use super::CompressedFormat;
-
 pub use imgse::Imgse;
 pub use imgtg::Imgtg;
 
-use super::{AllowedImageFormat, BaseManager, DeleteResponse, Extra, ImageItem, ManagerCode};
+use super::{
+    check_server_error, BaseManager, DeleteResponse, Extra, ImageItem, ManagerCode,
+    RequestWithBodyMethod, UploadRequest,
+};
 
 const MAX_RETRY_COUNT: u8 = 3;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct HistoryDataItem {
-    width: u16,
-    height: u16,
-    filename: String,
-    storename: String,
-    size: u64,
-    path: String,
-    hash: String,
-    created_at: String,
-    url: String,
-    delete: String,
-    page: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct HistoryResponse {
-    success: bool,
-    code: String,
-    message: String,
-    data: Vec<HistoryDataItem>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct CheveretoDeleteResponse {
-    status_code: u16,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct CheveretoUploadSuccess {
     message: String,
@@ -74,15 +49,15 @@ struct CheveretoImage {
     thumb: Thumb,
 }
 
-impl Into<CheveretoError> for CheveretoErrorDetail {
-    fn into(self) -> CheveretoError {
-        if self.message == "请求被拒绝 (auth_token)" {
+impl From<CheveretoErrorDetail> for CheveretoError {
+    fn from(detail: CheveretoErrorDetail) -> Self {
+        if detail.message == "请求被拒绝 (auth_token)" {
             return CheveretoError::AuthToken;
-        } else if self.message == "Invalid content owner request" {
+        } else if detail.message == "Invalid content owner request" {
             return CheveretoError::InvalidContentOwnerRequest;
         }
 
-        CheveretoError::Other(self.message)
+        CheveretoError::Other(detail.message)
     }
 }
 
@@ -92,15 +67,6 @@ struct CheveretoErrorDetail {
     code: u16,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CheveretoRequest {
-    r#type: String,
-    action: String,
-    timestamp: String,
-    auth_token: String,
-    nsfw: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct CheveretoErrorResponse {
     status_code: u16,
@@ -153,34 +119,19 @@ pub struct Chevereto {
 impl Chevereto {
     pub fn new<S: Into<String>>(
         code: ManagerCode,
-        name: &str,
-        base_url: &str,
+        inner: BaseManager,
+        file_kind: FileKind,
         username: S,
         password: S,
-        max_size: u8,
-        file_kind: FileKind,
-        allowed_formats: Vec<AllowedImageFormat>,
-        timeout: u8,
         extra: Option<&HashMap<String, String>>,
-        #[cfg(feature = "compress")] compressed_format: CompressedFormat,
     ) -> Self {
         let (token, cookie) = match extra {
             None => (None, None),
             Some(m) => (m.get("token"), m.get("cookie")),
         };
 
-        let manager = BaseManager::new(
-            name,
-            base_url,
-            max_size,
-            allowed_formats,
-            Some(timeout),
-            #[cfg(feature = "compress")]
-            compressed_format,
-        );
-
         Self {
-            inner: manager,
+            inner,
             file_part_name: "source".to_string(),
             file_kind,
             username: username.into(),
@@ -318,7 +269,8 @@ impl Chevereto {
 
         debug!("request: data = {:?}, headers = {:?}", params, headers);
 
-        let client = reqwest::ClientBuilder::new()
+        // 登录时需要禁止重定向，无法复用 BaseManager 中的客户端
+        let client = client_builder(self.inner.proxy.as_ref())?
             .redirect(reqwest::redirect::Policy::none())
             .build()?;
 
@@ -434,6 +386,7 @@ impl Chevereto {
                 url: image.url,
                 deleted_id: image.name,
                 thumb: Some(image.thumb.url),
+                manager: None,
             });
         }
 
@@ -595,16 +548,23 @@ impl Chevereto {
             .upload_multipart(
                 window.clone(),
                 id,
-                &url,
-                headers,
+                UploadRequest {
+                    method: RequestWithBodyMethod::Post,
+                    url: &url,
+                    headers,
+                },
                 image_path,
-                &self.file_part_name,
-                &self.file_kind,
+                FilePart {
+                    name: &self.file_part_name,
+                    kind: &self.file_kind,
+                },
                 Some(form),
             )
             .await?;
 
         let status = response.status();
+        check_server_error(status)?;
+
         if status != StatusCode::OK {
             let error_response = match response.json::<CheveretoErrorResponse>().await {
                 Ok(e) => e,
@@ -648,3 +608,67 @@ impl Chevereto {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes, http::StatusCode as AxumStatusCode, Router};
+
+    use super::*;
+    use crate::http::mock;
+    use crate::manager::AllowedImageFormat;
+    #[cfg(feature = "compress")]
+    use crate::manager::CompressedFormat;
+
+    async fn upload_with_status(status: u16, body: &'static str) -> Up2bError {
+        mock::init_config().await;
+
+        let status = AxumStatusCode::from_u16(status).unwrap();
+        let base_url =
+            mock::serve(Router::new().fallback(move |_: Bytes| async move { (status, body) }))
+                .await;
+
+        // 已有 token 和 cookie 时不会先登录
+        let extra = HashMap::from([
+            ("token".to_owned(), "token".to_owned()),
+            ("cookie".to_owned(), "PHPSESSID=cookie".to_owned()),
+        ]);
+        let inner = BaseManager::new(
+            "imgse.com",
+            &base_url,
+            10,
+            vec![AllowedImageFormat::Png],
+            Some(5),
+            #[cfg(feature = "compress")]
+            CompressedFormat::Jpeg,
+            None,
+        )
+        .unwrap();
+        let mut manager = Chevereto::new(
+            ManagerCode::Imgse,
+            inner,
+            FileKind::Buffer,
+            "user",
+            "password",
+            Some(&extra),
+        );
+
+        let image_path = mock::temp_file("a.png", b"png").await;
+        manager.upload(None, 0, &image_path, 0).await.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn server_error_is_retryable() {
+        let e = upload_with_status(500, "Internal Server Error").await;
+        assert!(e.is_retryable(), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn client_error_is_not_retryable() {
+        let e = upload_with_status(
+            400,
+            r#"{"status_code": 400, "status_txt": "Bad Request", "error": {"message": "Duplicated upload", "code": 101}}"#,
+        )
+        .await;
+        assert!(!e.is_retryable(), "{e:?}");
+    }
+}
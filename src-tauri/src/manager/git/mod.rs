@@ -1,57 +1,88 @@
+mod provider;
+
 use std::{collections::HashMap, path::Path, str::FromStr as _};
 
 use async_trait::async_trait;
 use reqwest::{
-    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION},
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT},
     Method, StatusCode,
 };
 use serde_json::Value;
 use tauri::WebviewWindow;
 
+use self::provider::encode_path;
+pub(crate) use self::provider::GitProvider;
+
 #[cfg(feature = "compress")]
 use super::CompressedFormat;
 use super::{
-    AllowedImageFormat, BaseManager, DeleteResponse, Extra, ImageItem, Manage,
-    RequestWithBodyMethod, UploadResult,
+    check_server_error, AllowedImageFormat, BaseManager, DeleteResponse, Extra, ImageItem, Manage,
+    RequestWithBodyMethod, UploadRequest, UploadResult,
 };
 
 use crate::{
-    error::{GitError, HeaderError, PathError, UploadError},
+    config::ProxyKind,
+    error::{GitError, HeaderError, PathError, TokenError, UploadError},
     manager::DeleteError,
-    util::time::now,
     Up2bError, Up2bResult,
 };
 
+/// gitlab 创建文件时必须指定分支
+const GITLAB_DEFAULT_BRANCH: &str = "main";
+/// gitlab 列表接口每页最大数量
+const GITLAB_PER_PAGE: usize = 100;
+
+/// 仓库配置，对应 ManagerAuthConfigKind::Git
+pub(super) struct GitRepository<'a> {
+    pub base_url: &'a str,
+    pub token: &'a str,
+    pub username: &'a str,
+    pub repository: &'a str,
+    /// 保存目录,默认为 up2b
+    pub path: Option<&'a str>,
+    pub branch: Option<&'a str>,
+    pub name_template: Option<&'a str>,
+}
+
 #[derive(Debug)]
 pub(super) struct GitManager {
+    /// base_url 为仓库 api 地址
     inner: BaseManager,
-    // auth_method: AuthMethod, // 当前只支持 github，认证方式暂时只用 headers
+    provider: GitProvider,
+    /// 仓库网页地址，用于拼接 gitlab 的原始文件链接
+    web_url: String,
     headers: Option<HashMap<String, String>>,
     token: String,
+    /// 保存目录
+    directory: String,
+    branch: Option<String>,
 }
 
 impl GitManager {
     pub(super) fn new<H: Into<Option<HashMap<String, String>>>>(
+        provider: GitProvider,
         name: &str,
-        base_url: &str,
-        // auth_method: AuthMethod,
+        repo: GitRepository,
         headers: H,
-        token: &str,
-        username: &str,
-        repository: &str,
-        path: Option<&str>,
         timeout: Option<u8>,
         max_size: u8,
-    ) -> Self {
-        let inner = BaseManager::new(
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let GitRepository {
+            base_url,
+            token,
+            username,
+            repository,
+            path,
+            branch,
+            name_template,
+        } = repo;
+
+        let api_url = provider.api_url(base_url);
+
+        let mut inner = BaseManager::new(
             name,
-            &format!(
-                "{}/repos/{}/{}/contents/{}",
-                base_url,
-                username,
-                repository,
-                path.unwrap_or("up2b")
-            ),
+            &provider.repository_url(&api_url, username, repository),
             max_size,
             vec![
                 AllowedImageFormat::Jpeg,
@@ -63,14 +94,30 @@ impl GitManager {
             ],
             timeout,
             #[cfg(feature = "compress")]
-            CompressedFormat::WEBP,
-        );
+            CompressedFormat::Webp,
+            proxy,
+        )?;
+        inner.name_template = name_template.map(|t| t.to_owned());
+
+        let branch = match provider {
+            GitProvider::Gitlab => Some(branch.unwrap_or(GITLAB_DEFAULT_BRANCH)),
+            _ => branch,
+        };
 
-        Self {
+        Ok(Self {
             inner,
+            provider,
+            web_url: format!(
+                "{}/{}/{}",
+                base_url.trim_end_matches('/'),
+                username,
+                repository
+            ),
             headers: headers.into(),
             token: token.into(),
-        }
+            directory: path.unwrap_or("up2b").trim_matches('/').to_owned(),
+            branch: branch.map(|b| b.to_owned()),
+        })
     }
 
     pub fn allowed_formats(&self) -> &[AllowedImageFormat] {
@@ -85,62 +132,129 @@ impl GitManager {
             }
             Some(map) => {
                 for (k, v) in map.iter() {
-                    let key = HeaderName::from_str(&k).map_err(HeaderError::InvalidName)?;
-                    let val = HeaderValue::from_str(&v).map_err(HeaderError::InvalidValue)?;
+                    let key = HeaderName::from_str(k).map_err(HeaderError::InvalidName)?;
+                    let val = HeaderValue::from_str(v).map_err(HeaderError::InvalidValue)?;
                     headers.insert(key, val);
                 }
             }
         }
 
+        let (key, value) = self.provider.auth_header(&self.token);
         headers.insert(
-            AUTHORIZATION,
-            ("Bearer ".to_owned() + &self.token).parse().unwrap(),
+            key,
+            HeaderValue::from_str(&value).map_err(HeaderError::InvalidValue)?,
         );
 
         debug!("请求头: {:?}", headers);
-        // if let AuthMethod::Header { key, prefix } = &self.auth_method {
-        //     let auth_key = key.as_deref().unwrap_or("Authorization");
-        //     let key = HeaderName::from_str(auth_key).map_err(HeaderError::InvalidName)?;
-        //
-        //     let token = match prefix {
-        //         None => self.token.clone(),
-        //         Some(p) => p.to_owned() + &self.token,
-        //     };
-        //
-        //     headers.insert(key, token.parse().unwrap());
-        // }
 
         Ok(headers)
     }
 
+    /// 文件在仓库中的路径
+    fn file_path(&self, filename: &str) -> String {
+        if self.directory.is_empty() {
+            return filename.to_owned();
+        }
+
+        format!("{}/{}", self.directory, filename)
+    }
+
+    /// 单个文件的 api 地址
+    fn file_url(&self, file_path: &str) -> String {
+        match self.provider {
+            GitProvider::Github | GitProvider::Gitea => {
+                self.inner.url(&format!("contents/{}", file_path))
+            }
+            GitProvider::Gitlab => self
+                .inner
+                .url(&format!("repository/files/{}", encode_path(file_path))),
+        }
+    }
+
+    /// gitlab 的原始文件链接
+    fn gitlab_raw_url(&self, file_path: &str) -> String {
+        format!(
+            "{}/-/raw/{}/{}",
+            self.web_url,
+            self.branch.as_deref().unwrap_or(GITLAB_DEFAULT_BRANCH),
+            file_path
+        )
+    }
+
     fn parse_images(&self, items: &[Value]) -> Up2bResult<Vec<ImageItem>> {
         let mut image_items = Vec::with_capacity(items.len());
 
         for item in items.iter() {
-            let download_url = item.get_string("download_url")?;
-            let sha = item.get_string("sha")?;
-            let url = item.get_string("url")?;
-            image_items.push(ImageItem {
-                url: download_url,
-                deleted_id: format!("{}---{}", url, sha),
-                thumb: None,
-            })
+            match self.provider {
+                GitProvider::Github | GitProvider::Gitea => {
+                    // 跳过符号链接和子模块
+                    if item["type"] != "file" {
+                        continue;
+                    }
+
+                    let download_url = item.get_string("download_url")?;
+                    let sha = item.get_string("sha")?;
+                    let url = item.get_string("url")?;
+                    image_items.push(ImageItem {
+                        url: download_url,
+                        deleted_id: format!("{}---{}", url, sha),
+                        thumb: None,
+                        manager: None,
+                    })
+                }
+                GitProvider::Gitlab => {
+                    if item["type"] != "blob" {
+                        continue;
+                    }
+
+                    let sha = item.get_string("id")?;
+                    let path = item.get_string("path")?;
+                    image_items.push(ImageItem {
+                        url: self.gitlab_raw_url(&path),
+                        deleted_id: format!("{}---{}", self.file_url(&path), sha),
+                        thumb: None,
+                        manager: None,
+                    })
+                }
+            }
         }
 
         Ok(image_items)
     }
 
-    pub async fn list(&self) -> Up2bResult<Vec<ImageItem>> {
-        let resp = self
-            .inner
-            .get(&self.inner.base_url, self.headers()?)
-            .await?;
+    /// github 和 gitea 的目录内容，不包含子目录中的文件
+    async fn list_directory(&self, directory: &str) -> Up2bResult<Vec<Value>> {
+        let url = self.inner.url(&format!("contents/{}", directory));
+        let url = match &self.branch {
+            None => url,
+            Some(b) => format!("{}?ref={}", url, encode_path(b)),
+        };
+
+        self.list_items(&url).await
+    }
+
+    /// gitlab 递归列出保存目录中的文件
+    async fn list_page(&self, page: usize) -> Up2bResult<Vec<Value>> {
+        let url = format!(
+            "{}?path={}&ref={}&recursive=true&per_page={}&page={}",
+            self.inner.url("repository/tree"),
+            encode_path(&self.directory),
+            encode_path(self.branch.as_deref().unwrap_or(GITLAB_DEFAULT_BRANCH)),
+            GITLAB_PER_PAGE,
+            page
+        );
+
+        self.list_items(&url).await
+    }
+
+    async fn list_items(&self, url: &str) -> Up2bResult<Vec<Value>> {
+        let resp = self.inner.get(url, self.headers()?).await?;
 
         let status = resp.status();
         let json: Value = resp.json().await?;
 
         if status != StatusCode::OK {
-            let message = json.get_string("message")?;
+            let message = self.provider.error_message(&json);
             error!("获取图片列表错误，状态码：{}，错误：{}", status, message);
 
             let error: GitError = message.into();
@@ -149,11 +263,50 @@ impl GitManager {
         }
 
         match json {
-            Value::Array(items) => self.parse_images(&items),
+            Value::Array(items) => Ok(items),
             _ => unreachable!(),
         }
     }
 
+    pub async fn list(&self) -> Up2bResult<Vec<ImageItem>> {
+        let items = match self.provider {
+            // 只有 gitlab 的列表接口是分页的
+            GitProvider::Gitlab => {
+                let mut items = self.list_page(1).await?;
+                let mut page = 1;
+                let mut count = items.len();
+
+                while count == GITLAB_PER_PAGE {
+                    page += 1;
+                    let next = self.list_page(page).await?;
+                    count = next.len();
+                    items.extend(next);
+                }
+
+                items
+            }
+            // 目录内容接口不会递归，逐层获取子目录
+            GitProvider::Github | GitProvider::Gitea => {
+                let mut items = Vec::new();
+                let mut directories = vec![self.directory.clone()];
+
+                while let Some(directory) = directories.pop() {
+                    for item in self.list_directory(&directory).await? {
+                        if item["type"] == "dir" {
+                            directories.push(item.get_string("path")?);
+                        } else {
+                            items.push(item);
+                        }
+                    }
+                }
+
+                items
+            }
+        };
+
+        self.parse_images(&items)
+    }
+
     const DELETE_MESSAGE: &'static str = "up2b: delete the picture that is no longer used";
 
     pub async fn delete(&self, s: &str) -> Up2bResult<DeleteResponse> {
@@ -162,7 +315,17 @@ impl GitManager {
         let url = v[0];
         let sha = v[1];
 
-        let data = HashMap::from([("sha", sha), ("message", Self::DELETE_MESSAGE)]);
+        let mut data = match self.provider {
+            GitProvider::Github | GitProvider::Gitea => {
+                HashMap::from([("sha", sha), ("message", Self::DELETE_MESSAGE)])
+            }
+            // gitlab 删除文件不需要 sha
+            GitProvider::Gitlab => HashMap::from([("commit_message", Self::DELETE_MESSAGE)]),
+        };
+
+        if let Some(branch) = &self.branch {
+            data.insert("branch", branch.as_str());
+        }
 
         let resp = self
             .inner
@@ -172,9 +335,9 @@ impl GitManager {
             .await?;
 
         let status = resp.status();
-        if status != StatusCode::OK {
+        if status != self.provider.deleted_status() {
             let json: Value = resp.json().await?;
-            let message = json.get_string("message")?;
+            let message = self.provider.error_message(&json);
             error!("删除失败，状态码：{}，错误：{}", status, message);
 
             // TODO: 以后处理图片不存在的错误
@@ -190,6 +353,85 @@ impl GitManager {
         })
     }
 
+    /// 获取仓库信息，验证 token 是否有效以及是否有推送权限
+    pub async fn verify(&self) -> Up2bResult<Option<Extra>> {
+        let resp = self
+            .inner
+            .get(&self.inner.base_url, self.headers()?)
+            .await?;
+
+        let status = resp.status();
+        check_server_error(status)?;
+
+        // 认证失败时的响应体不一定是 json
+        let text = resp.text().await?;
+        let json: Value =
+            serde_json::from_str(&text).unwrap_or_else(|_| serde_json::json!({ "message": text }));
+
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            let message = self.provider.error_message(&json);
+            error!("token 无效：{}", message);
+
+            return Err(TokenError::Invalid(message).into());
+        }
+
+        if status != StatusCode::OK {
+            let message = self.provider.error_message(&json);
+            error!("获取仓库信息错误，状态码：{}，错误：{}", status, message);
+
+            let error: GitError = message.into();
+
+            return Err(error.into());
+        }
+
+        let full_name = json.get_string(self.provider.full_name_key())?;
+
+        if !self.provider.can_push(&json) {
+            error!("token 没有仓库 {} 的推送权限", full_name);
+
+            return Err(TokenError::NoPermission(full_name).into());
+        }
+
+        let mut extra = Extra::from([("repository".to_owned(), full_name)]);
+
+        if let Value::String(b) = &json["default_branch"] {
+            extra.insert("default_branch".to_owned(), b.to_owned());
+        }
+
+        info!("token 验证通过：{:?}", extra);
+
+        Ok(Some(extra))
+    }
+
+    /// gitlab 创建文件的响应中没有 blob id，需要通过 HEAD 请求获取
+    async fn gitlab_blob_id(&self, file_url: &str) -> Up2bResult<String> {
+        let url = format!(
+            "{}?ref={}",
+            file_url,
+            encode_path(self.branch.as_deref().unwrap_or(GITLAB_DEFAULT_BRANCH))
+        );
+
+        let resp = self
+            .inner
+            .request(Method::HEAD, &url, self.headers()?)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if status != StatusCode::OK {
+            error!("获取 blob id 失败，状态码：{}", status);
+            return Err(Up2bError::Status(status));
+        }
+
+        match resp.headers().get("X-Gitlab-Blob-Id") {
+            Some(v) => Ok(v
+                .to_str()
+                .map_err(|e| Up2bError::Other(e.to_string()))?
+                .to_owned()),
+            None => Err(Up2bError::KeyNotFound("X-Gitlab-Blob-Id".to_owned())),
+        }
+    }
+
     async fn upload(
         &self,
         window: Option<WebviewWindow>,
@@ -201,23 +443,44 @@ impl GitManager {
             None => return Err(PathError::NotFile.into()),
         };
 
-        let now = now()?;
+        let message = "up2b: ".to_owned() + &filename;
 
-        let form = HashMap::from([("message".to_owned(), "up2b: ".to_owned() + &filename)]);
+        let file_path = self.file_path(&self.inner.file_name(image_path).await?);
+        let url = self.file_url(&file_path);
 
-        let parts: Vec<&str> = filename.rsplitn(2, '.').collect();
+        let (method, mut form) = match self.provider {
+            GitProvider::Github => (
+                RequestWithBodyMethod::Put,
+                HashMap::from([("message".to_owned(), message)]),
+            ),
+            // gitea 中 PUT 用于更新文件，创建文件需用 POST
+            GitProvider::Gitea => (
+                RequestWithBodyMethod::Post,
+                HashMap::from([("message".to_owned(), message)]),
+            ),
+            GitProvider::Gitlab => (
+                RequestWithBodyMethod::Post,
+                HashMap::from([
+                    ("commit_message".to_owned(), message),
+                    ("encoding".to_owned(), "base64".to_owned()),
+                ]),
+            ),
+        };
 
-        let filename_with_timestamp = format!("{}_{}.{}", parts[1], now.as_millis(), parts[0]);
+        if let Some(branch) = &self.branch {
+            form.insert("branch".to_owned(), branch.to_owned());
+        }
 
-        let url = &self.inner.url(&filename_with_timestamp);
         let resp = self
             .inner
             .upload_json(
                 window,
-                RequestWithBodyMethod::PUT,
                 id,
-                url,
-                self.headers()?,
+                UploadRequest {
+                    method,
+                    url: &url,
+                    headers: self.headers()?,
+                },
                 "content",
                 image_path,
                 Some(form),
@@ -225,26 +488,44 @@ impl GitManager {
             .await?;
 
         let status = resp.status();
+        check_server_error(status)?;
+
         let json: Value = resp.json().await?;
         if status != StatusCode::CREATED {
-            let message = json.get_string("message")?;
+            let message = self.provider.error_message(&json);
             error!("上传失败，状态码：{}，错误：{}", status, message);
 
             return Err(UploadError::Error(message).into());
         }
 
-        let content = &json["content"];
+        let (download_url, deleted_id) = match self.provider {
+            GitProvider::Github | GitProvider::Gitea => {
+                let content = &json["content"];
 
-        let download_url = content.get_string("download_url")?;
-        let sha = content.get_string("sha")?;
-        let url = content.get_string("url")?;
+                let download_url = content.get_string("download_url")?;
+                let sha = content.get_string("sha")?;
+                let url = content.get_string("url")?;
+
+                (download_url, format!("{}---{}", url, sha))
+            }
+            GitProvider::Gitlab => {
+                let file_path = json.get_string("file_path")?;
+                let sha = self.gitlab_blob_id(&url).await?;
+
+                (
+                    self.gitlab_raw_url(&file_path),
+                    format!("{}---{}", url, sha),
+                )
+            }
+        };
 
         info!("图片已上传：path={:?}, url={}", image_path, download_url);
 
         Ok(UploadResult::Response(ImageItem {
             url: download_url,
-            deleted_id: format!("{}---{}", url, sha),
+            deleted_id,
             thumb: None,
+            manager: None,
         }))
     }
 }
@@ -258,8 +539,8 @@ impl ValueGetter for Value {
     fn get_string(&self, key: &str) -> Up2bResult<String> {
         match &self[key] {
             Value::String(s) => Ok(s.to_owned()),
-            Value::Null => return Err(Up2bError::KeyNotFound(key.to_owned())),
-            _ => return Err(Up2bError::KeyNotMatch(key.to_owned())),
+            Value::Null => Err(Up2bError::KeyNotFound(key.to_owned())),
+            _ => Err(Up2bError::KeyNotMatch(key.to_owned())),
         }
     }
 
@@ -273,18 +554,22 @@ impl ValueGetter for Value {
 }
 
 #[async_trait]
-impl<'r> Manage for GitManager {
+impl Manage for GitManager {
     fn allowed_formats(&self) -> Vec<AllowedImageFormat> {
         self.allowed_formats().to_owned()
     }
 
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.compressed_format = format;
+    }
+
     fn support_stream(&self) -> bool {
         true
     }
 
     async fn verify(&self) -> Up2bResult<Option<Extra>> {
-        // TODO: 验证以后再实现
-        Ok(None)
+        GitManager::verify(self).await
     }
 
     async fn get_all_images(&self) -> Up2bResult<Vec<ImageItem>> {
@@ -318,3 +603,162 @@ impl<'r> Manage for GitManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Bytes,
+        http::{StatusCode as AxumStatusCode, Uri},
+        Router,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::http::mock;
+
+    async fn manager_with_status(status: u16, body: &'static str) -> GitManager {
+        let status = AxumStatusCode::from_u16(status).unwrap();
+        let base_url =
+            mock::serve(Router::new().fallback(move |_: Bytes| async move { (status, body) }))
+                .await;
+
+        manager(GitProvider::Github, &base_url)
+    }
+
+    fn manager(provider: GitProvider, base_url: &str) -> GitManager {
+        GitManager::new(
+            provider,
+            "git",
+            GitRepository {
+                base_url,
+                token: "token",
+                username: "user",
+                repository: "repo",
+                path: None,
+                branch: None,
+                name_template: None,
+            },
+            None,
+            Some(5),
+            20,
+            None,
+        )
+        .unwrap()
+    }
+
+    async fn upload_with_status(status: u16, body: &'static str) -> Up2bError {
+        mock::init_config().await;
+
+        let image_path = mock::temp_file("a.png", b"png").await;
+        manager_with_status(status, body)
+            .await
+            .upload(None, 0, &image_path)
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn server_error_is_retryable() {
+        let e = upload_with_status(503, "Service Unavailable").await;
+        assert!(e.is_retryable(), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn client_error_is_not_retryable() {
+        let e = upload_with_status(422, r#"{"message": "Invalid request"}"#).await;
+        assert!(!e.is_retryable(), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn verify_maps_auth_failures_to_token_error() {
+        for (status, body) in [
+            (401, r#"{"message": "Bad credentials"}"#),
+            (403, "Forbidden"),
+        ] {
+            let e = manager_with_status(status, body)
+                .await
+                .verify()
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(e, Up2bError::Token(TokenError::Invalid(_))),
+                "{e:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_server_error_is_status_error() {
+        let e = manager_with_status(502, "<html>Bad Gateway</html>")
+            .await
+            .verify()
+            .await
+            .unwrap_err();
+        assert!(matches!(e, Up2bError::Status(_)), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn github_list_walks_nested_directories() {
+        let base_url = mock::serve(Router::new().fallback(|uri: Uri| async move {
+            let file = |path: &str| {
+                json!({
+                    "type": "file",
+                    "path": path,
+                    "sha": "sha",
+                    "url": format!("http://api/{}", path),
+                    "download_url": format!("http://raw/{}", path),
+                })
+            };
+            let items = match uri.path() {
+                "/repos/user/repo/contents/up2b" => json!([
+                    file("up2b/a.png"),
+                    {"type": "dir", "path": "up2b/2024"},
+                ]),
+                "/repos/user/repo/contents/up2b/2024" => json!([file("up2b/2024/b.png")]),
+                _ => return (AxumStatusCode::NOT_FOUND, String::new()),
+            };
+            (AxumStatusCode::OK, items.to_string())
+        }))
+        .await;
+
+        let mut urls: Vec<String> = manager(GitProvider::Github, &base_url)
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.url)
+            .collect();
+        urls.sort();
+
+        assert_eq!(
+            urls,
+            ["http://raw/up2b/2024/b.png", "http://raw/up2b/a.png"]
+        );
+    }
+
+    #[tokio::test]
+    async fn gitlab_list_is_recursive() {
+        let base_url = mock::serve(Router::new().fallback(|uri: Uri| async move {
+            if !uri.query().unwrap_or_default().contains("recursive=true") {
+                return (AxumStatusCode::BAD_REQUEST, String::new());
+            }
+            let items = json!([
+                {"type": "tree", "path": "up2b/2024", "id": "tree"},
+                {"type": "blob", "path": "up2b/2024/b.png", "id": "sha"},
+            ]);
+            (AxumStatusCode::OK, items.to_string())
+        }))
+        .await;
+
+        let items = manager(GitProvider::Gitlab, &base_url)
+            .list()
+            .await
+            .unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].url,
+            format!("{}/user/repo/-/raw/main/up2b/2024/b.png", base_url)
+        );
+    }
+}
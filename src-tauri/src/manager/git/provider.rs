@@ -0,0 +1,98 @@
+use reqwest::header::{HeaderName, AUTHORIZATION};
+use serde_json::Value;
+
+use crate::util::url::percent_encode;
+
+/// git 托管平台，各平台的仓库文件 api 不同
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GitProvider {
+    Github,
+    Gitlab,
+    /// Forgejo 是 Gitea 的分支，api 与 Gitea 相同
+    Gitea,
+}
+
+impl GitProvider {
+    /// 平台 api 的根路径
+    pub(super) fn api_url(&self, base_url: &str) -> String {
+        let base_url = base_url.trim_end_matches('/');
+
+        match self {
+            GitProvider::Github => base_url.to_owned(),
+            GitProvider::Gitlab => base_url.to_owned() + "/api/v4",
+            GitProvider::Gitea => base_url.to_owned() + "/api/v1",
+        }
+    }
+
+    /// 仓库 api 地址
+    pub(super) fn repository_url(&self, api_url: &str, username: &str, repository: &str) -> String {
+        match self {
+            GitProvider::Github | GitProvider::Gitea => {
+                format!("{}/repos/{}/{}", api_url, username, repository)
+            }
+            // gitlab 使用 url 编码后的“命名空间/项目名”作为项目 id
+            GitProvider::Gitlab => format!(
+                "{}/projects/{}",
+                api_url,
+                encode_path(&format!("{}/{}", username, repository))
+            ),
+        }
+    }
+
+    /// 认证请求头
+    pub(super) fn auth_header(&self, token: &str) -> (HeaderName, String) {
+        match self {
+            GitProvider::Github => (AUTHORIZATION, "Bearer ".to_owned() + token),
+            GitProvider::Gitlab => (HeaderName::from_static("private-token"), token.to_owned()),
+            GitProvider::Gitea => (AUTHORIZATION, "token ".to_owned() + token),
+        }
+    }
+
+    /// 上传成功时的状态码都是 201，删除成功时 gitlab 返回 204，其他平台返回 200
+    pub(super) fn deleted_status(&self) -> reqwest::StatusCode {
+        match self {
+            GitProvider::Gitlab => reqwest::StatusCode::NO_CONTENT,
+            _ => reqwest::StatusCode::OK,
+        }
+    }
+
+    /// 仓库全名在仓库信息中的 key
+    pub(super) fn full_name_key(&self) -> &'static str {
+        match self {
+            GitProvider::Gitlab => "path_with_namespace",
+            _ => "full_name",
+        }
+    }
+
+    /// 根据仓库信息判断 token 是否有推送权限，gitlab 需要 developer（30）及以上的权限
+    pub(super) fn can_push(&self, repository: &Value) -> bool {
+        let permissions = &repository["permissions"];
+
+        match self {
+            GitProvider::Gitlab => ["project_access", "group_access"].iter().any(|k| {
+                permissions[k]["access_level"]
+                    .as_u64()
+                    .is_some_and(|l| l >= 30)
+            }),
+            _ => permissions["push"].as_bool().unwrap_or(false),
+        }
+    }
+
+    /// 从错误响应体中获取错误信息，gitlab 的错误信息可能在 message 或 error 中
+    pub(super) fn error_message(&self, json: &Value) -> String {
+        for key in ["message", "error"] {
+            match &json[key] {
+                Value::String(s) => return s.to_owned(),
+                Value::Null => {}
+                v => return v.to_string(),
+            }
+        }
+
+        json.to_string()
+    }
+}
+
+/// 对路径进行 url 编码，`/` 也会被编码
+pub(super) fn encode_path(path: &str) -> String {
+    percent_encode(path, false)
+}
@@ -0,0 +1,358 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use tauri::WebviewWindow;
+use tokio::fs::{self, File, OpenOptions};
+
+#[cfg(feature = "compress")]
+use super::CompressedFormat;
+use super::{
+    remove_compressed, AllowedImageFormat, BaseManager, DeleteError, DeleteResponse, Extra,
+    ImageItem, Manage, UploadResult,
+};
+
+use crate::{error::PathError, util::url::percent_encode, Up2bError, Up2bResult};
+
+/// 同名文件已存在时，最多尝试的序号
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+/// 在扩展名之前添加序号，序号为 0 时返回原文件名
+fn numbered_name(name: &str, index: usize) -> String {
+    if index == 0 {
+        return name.to_owned();
+    }
+
+    // 只在最后一级文件名中查找扩展名，且不把隐藏文件的点当作扩展名
+    let file_start = name.rfind('/').map_or(0, |i| i + 1);
+
+    match name.rfind('.').filter(|i| *i > file_start) {
+        Some(dot) => format!("{}_{}{}", &name[..dot], index, &name[dot..]),
+        None => format!("{}_{}", name, index),
+    }
+}
+
+/// 将图片保存到本地目录的图床，适用于由 web 服务器托管的静态网站目录
+#[derive(Debug)]
+pub(super) struct LocalManager {
+    /// base_url 为图片的公开访问地址前缀
+    inner: BaseManager,
+    directory: PathBuf,
+}
+
+impl LocalManager {
+    pub(super) fn new(
+        name: &str,
+        directory: &str,
+        url_prefix: &str,
+        name_template: Option<&str>,
+    ) -> Up2bResult<Self> {
+        let mut inner = BaseManager::new(
+            name,
+            url_prefix.trim_end_matches('/'),
+            u8::MAX,
+            vec![
+                AllowedImageFormat::Jpeg,
+                AllowedImageFormat::Png,
+                AllowedImageFormat::Gif,
+                AllowedImageFormat::Bmp,
+                AllowedImageFormat::Webp,
+                AllowedImageFormat::Avif,
+            ],
+            None,
+            #[cfg(feature = "compress")]
+            CompressedFormat::Webp,
+            None,
+        )?;
+        inner.name_template = name_template.map(|t| t.to_owned());
+
+        Ok(Self {
+            inner,
+            directory: PathBuf::from(directory),
+        })
+    }
+
+    /// 创建新文件，同名文件已存在时在文件名后添加序号，不覆盖已有的图片。
+    ///
+    /// 返回实际使用的文件名
+    async fn create_new(&self, name: &str) -> Up2bResult<(String, File)> {
+        for i in 0..MAX_RENAME_ATTEMPTS {
+            let candidate = numbered_name(name, i);
+
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.directory.join(&candidate))
+                .await
+            {
+                Ok(f) => return Ok((candidate, f)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    debug!("文件已存在：{}", candidate)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(Up2bError::Other(format!("同名文件过多：{}", name)))
+    }
+
+    /// 相对路径统一使用 `/` 分隔，作为删除 id
+    fn relative_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.directory).ok()?;
+
+        let parts: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        Some(parts.join("/"))
+    }
+
+    fn image_item(&self, relative_path: String) -> ImageItem {
+        ImageItem {
+            url: self.inner.url(&percent_encode(&relative_path, true)),
+            deleted_id: relative_path,
+            thumb: None,
+            manager: None,
+        }
+    }
+
+    pub async fn list(&self) -> Up2bResult<Vec<ImageItem>> {
+        let mut images = Vec::new();
+        let mut dirs = vec![self.directory.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let file_type = entry.file_type().await?;
+
+                if file_type.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let is_image = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .and_then(AllowedImageFormat::from_extension)
+                    .is_some();
+
+                if !file_type.is_file() || !is_image {
+                    continue;
+                }
+
+                if let Some(relative_path) = self.relative_path(&path) {
+                    images.push(self.image_item(relative_path));
+                }
+            }
+        }
+
+        debug!("图片列表：{:?}", images);
+
+        Ok(images)
+    }
+
+    pub async fn delete(&self, id: &str) -> Up2bResult<DeleteResponse> {
+        let path = self.directory.join(id);
+
+        if !path.exists() {
+            return Ok(DeleteResponse {
+                success: false,
+                error: Some(DeleteError::NotFound),
+            });
+        }
+
+        // 防止通过 `..` 删除目录之外的文件
+        let directory = fs::canonicalize(&self.directory).await?;
+        let path = fs::canonicalize(&path).await?;
+        if !path.starts_with(&directory) || !path.is_file() {
+            error!("拒绝删除图片目录之外的文件：{:?}", path);
+            return Ok(DeleteResponse {
+                success: false,
+                error: Some(DeleteError::Other(PathError::NotFile.to_string())),
+            });
+        }
+
+        fs::remove_file(&path).await?;
+
+        info!("已删除图片：{:?}", path);
+
+        Ok(DeleteResponse {
+            success: true,
+            error: None,
+        })
+    }
+
+    async fn upload(
+        &self,
+        window: Option<WebviewWindow>,
+        image_path: &Path,
+    ) -> Up2bResult<UploadResult> {
+        #[cfg(feature = "compress")]
+        let filename = match image_path.file_name() {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => return Err(PathError::NotFile.into()),
+        };
+
+        let file = File::open(image_path).await?;
+        let (mut file, upload_path) = self
+            .inner
+            .compress(
+                #[cfg(feature = "compress")]
+                window.as_ref(),
+                file,
+                image_path,
+                #[cfg(feature = "compress")]
+                &filename,
+            )
+            .await?;
+
+        #[cfg(not(feature = "compress"))]
+        let _ = window;
+
+        // 压缩后扩展名可能改变，使用实际保存的文件生成文件名
+        let name = self.inner.file_name(&upload_path).await?;
+
+        // 文件名模板中可能包含子目录
+        if let Some(parent) = self.directory.join(&name).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let saved: Up2bResult<String> = async {
+            let (name, mut target_file) = self.create_new(&name).await?;
+            tokio::io::copy(&mut file, &mut target_file).await?;
+            Ok(name)
+        }
+        .await;
+
+        // windows 中打开的文件所在目录无法删除
+        drop(file);
+        remove_compressed(image_path, &upload_path).await;
+
+        let name = saved?;
+        let target = self.directory.join(&name);
+
+        let item = self.image_item(name);
+
+        info!(
+            "图片已保存：path={:?}, target={:?}, url={}",
+            image_path, target, item.url
+        );
+
+        Ok(UploadResult::Response(item))
+    }
+}
+
+#[async_trait]
+impl Manage for LocalManager {
+    fn allowed_formats(&self) -> Vec<AllowedImageFormat> {
+        self.inner.allowed_formats.clone()
+    }
+
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.compressed_format = format;
+    }
+
+    /// 本地复制没有上传进度
+    fn support_stream(&self) -> bool {
+        false
+    }
+
+    async fn verify(&self) -> Up2bResult<Option<Extra>> {
+        if !self.directory.exists() {
+            return Err(PathError::NotExists.into());
+        }
+
+        if !self.directory.is_dir() {
+            return Err(Up2bError::Other(format!(
+                "{} 不是目录",
+                self.directory.to_string_lossy()
+            )));
+        }
+
+        Ok(Some(Extra::from([(
+            "directory".to_owned(),
+            self.directory.to_string_lossy().to_string(),
+        )])))
+    }
+
+    async fn get_all_images(&self) -> Up2bResult<Vec<ImageItem>> {
+        self.list().await
+    }
+
+    async fn delete_image(&self, id: &str) -> Up2bResult<DeleteResponse> {
+        match self.delete(id).await {
+            Ok(r) => Ok(r),
+            Err(e) => Ok(DeleteResponse {
+                success: false,
+                error: Some(DeleteError::Other(e.to_string())),
+            }),
+        }
+    }
+
+    async fn upload_image(
+        &self,
+        window: Option<WebviewWindow>,
+        _id: u32,
+        image_path: &Path,
+    ) -> UploadResult {
+        match self.upload(window, image_path).await {
+            Ok(r) => r,
+            Err(e) => {
+                return UploadResult::Error {
+                    code: e.as_string(),
+                    detail: e,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock;
+
+    #[test]
+    fn numbers_before_extension() {
+        assert_eq!(numbered_name("a.png", 0), "a.png");
+        assert_eq!(numbered_name("a.png", 2), "a_2.png");
+        assert_eq!(numbered_name("2024.01/a", 1), "2024.01/a_1");
+        assert_eq!(numbered_name("dir/.png", 1), "dir/.png_1");
+    }
+
+    #[tokio::test]
+    async fn does_not_overwrite_existing_files() {
+        mock::init_config().await;
+
+        let image_path = mock::temp_file("a.png", b"first").await;
+        let directory = image_path.parent().unwrap().join("images");
+
+        let manager = LocalManager::new(
+            "local",
+            &directory.to_string_lossy(),
+            "https://example.com",
+            Some("{filename}"),
+        )
+        .unwrap();
+
+        let mut urls = Vec::new();
+        for _ in 0..2 {
+            match manager.upload(None, &image_path).await.unwrap() {
+                UploadResult::Response(item) => urls.push(item.url),
+                UploadResult::Error { detail, .. } => panic!("{detail}"),
+            }
+        }
+
+        assert_eq!(
+            urls,
+            ["https://example.com/a.png", "https://example.com/a_1.png"]
+        );
+        assert!(directory.join("a_1.png").exists());
+    }
+}
@@ -1,34 +1,48 @@
 pub mod api;
 pub mod chevereto;
 pub mod git;
+pub mod local;
+pub mod s3;
 pub mod smms;
 
 use async_trait::async_trait;
-use base64::{engine::general_purpose, Engine as _};
-use reqwest::{header::HeaderMap, Client, Method, RequestBuilder, Response};
+use reqwest::{header::HeaderMap, Client, Method, RequestBuilder, Response, StatusCode};
 use serde::{de::Visitor, Deserialize, Serialize};
 use serde_json::{Map, Value};
-use std::{collections::HashMap, fmt::Display, path::Path, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tauri::WebviewWindow;
-use tokio::fs::{read, File};
+use tokio::fs::File;
 
 use crate::{
-    config::ManagerAuthConfigKind,
-    error::{ConfigError, Up2bError},
+    config::{ManagerAuthConfigKind, ProxyKind},
+    error::{is_retryable_status, ConfigError, Up2bError},
     http::{
-        json,
-        multipart::{self, FileKind, UploadFile},
+        client_builder, json,
+        multipart::{self, FilePart, UploadFile},
+    },
+    util::{
+        image::{guess_mime_type_by_ext, remove_temp_image},
+        naming::{render_file_name, DEFAULT_NAME_TEMPLATE},
     },
-    util::image::guess_mime_type_by_ext,
     Up2bResult,
 };
 #[cfg(feature = "compress")]
-use {crate::config::CONFIG, crate::util::image::compress::compress};
+use {
+    crate::config::CONFIG,
+    crate::util::image::compress::{compress, CompressTarget},
+};
 
 use self::{
     api::BaseApiManager,
     chevereto::{Imgse, Imgtg},
-    git::GitManager,
+    git::{GitManager, GitProvider, GitRepository},
+    local::LocalManager,
+    s3::{S3Bucket, S3Manager},
     smms::SmMs,
 };
 
@@ -37,6 +51,36 @@ pub struct ImageItem {
     pub url: String,
     deleted_id: String,
     thumb: Option<String>,
+    /// 上传图片的图床，只在上传结果中存在
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manager: Option<ManagerCode>,
+}
+
+impl ImageItem {
+    pub fn new(url: String, deleted_id: String, thumb: Option<String>) -> Self {
+        Self {
+            url,
+            deleted_id,
+            thumb,
+            manager: None,
+        }
+    }
+
+    pub fn set_manager(&mut self, manager: ManagerCode) {
+        self.manager = Some(manager);
+    }
+
+    pub fn deleted_id(&self) -> &str {
+        &self.deleted_id
+    }
+
+    pub fn thumb(&self) -> Option<&str> {
+        self.thumb.as_deref()
+    }
+
+    pub fn set_thumb(&mut self, thumb: String) {
+        self.thumb = Some(thumb);
+    }
 }
 
 #[derive(Debug)]
@@ -52,7 +96,7 @@ impl Serialize for DeleteError {
     {
         let s = match self {
             Self::NotFound => "图片不存在",
-            Self::Other(s) => &s,
+            Self::Other(s) => s,
         };
         serializer.serialize_str(s)
     }
@@ -64,6 +108,19 @@ pub struct DeleteResponse {
     error: Option<DeleteError>,
 }
 
+impl DeleteResponse {
+    pub fn ok() -> Self {
+        Self {
+            success: true,
+            error: None,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.success
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(tag = "type")]
 pub enum UploadResult {
@@ -76,6 +133,9 @@ pub type Extra = HashMap<String, String>;
 #[async_trait]
 pub trait Manage: Sync + Send {
     fn allowed_formats(&self) -> Vec<AllowedImageFormat>;
+    /// 使用配置中指定的压缩格式替换图床默认的压缩格式
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat);
     /// 上传时是否使用流，只有流式上传时前端才使用进度条
     fn support_stream(&self) -> bool;
     async fn verify(&self) -> Up2bResult<Option<Extra>>;
@@ -89,7 +149,7 @@ pub trait Manage: Sync + Send {
     ) -> UploadResult;
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum AllowedImageFormat {
     Jpeg,
@@ -100,21 +160,78 @@ pub enum AllowedImageFormat {
     Bmp,
 }
 
-#[cfg(feature = "compress")]
+impl AllowedImageFormat {
+    pub fn all() -> [Self; 6] {
+        [
+            AllowedImageFormat::Jpeg,
+            AllowedImageFormat::Png,
+            AllowedImageFormat::Webp,
+            AllowedImageFormat::Avif,
+            AllowedImageFormat::Gif,
+            AllowedImageFormat::Bmp,
+        ]
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AllowedImageFormat::Jpeg => "jpg",
+            AllowedImageFormat::Png => "png",
+            AllowedImageFormat::Webp => "webp",
+            AllowedImageFormat::Avif => "avif",
+            AllowedImageFormat::Gif => "gif",
+            AllowedImageFormat::Bmp => "bmp",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(AllowedImageFormat::Jpeg),
+            "png" => Some(AllowedImageFormat::Png),
+            "webp" => Some(AllowedImageFormat::Webp),
+            "avif" => Some(AllowedImageFormat::Avif),
+            "gif" => Some(AllowedImageFormat::Gif),
+            "bmp" => Some(AllowedImageFormat::Bmp),
+            _ => None,
+        }
+    }
+}
+
+/// 未启用 compress 时也保留，以免配置文件中的压缩格式在保存时丢失
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum CompressedFormat {
-    JPEG,
-    WEBP,
+    Jpeg,
+    Webp,
+    Avif,
+    /// 无损优化，仍超过限制时减少颜色数量，适合截图
+    Png,
+}
+
+#[cfg(feature = "compress")]
+impl CompressedFormat {
+    pub fn image_format(&self) -> AllowedImageFormat {
+        match self {
+            CompressedFormat::Jpeg => AllowedImageFormat::Jpeg,
+            CompressedFormat::Webp => AllowedImageFormat::Webp,
+            CompressedFormat::Avif => AllowedImageFormat::Avif,
+            CompressedFormat::Png => AllowedImageFormat::Png,
+        }
+    }
 }
 
 pub fn use_manager(
     using: &ManagerCode,
     auth_config: &ManagerAuthConfigKind,
+    proxy: Option<&ProxyKind>,
 ) -> Up2bResult<Box<dyn Manage>> {
     let uploader: Box<dyn Manage> = match using {
         ManagerCode::Smms => match auth_config {
-            ManagerAuthConfigKind::API { token, .. } => {
-                let manager = SmMs::new(token.to_string());
+            ManagerAuthConfigKind::Api {
+                token,
+                name_template,
+                ..
+            } => {
+                let manager = SmMs::new(token.to_string(), name_template.as_deref(), proxy)?;
                 Box::new(manager)
             }
             _ => return Err(Up2bError::Config(ConfigError::Type(using.name()))),
@@ -126,7 +243,7 @@ pub fn use_manager(
                 timeout,
                 extra,
             } => {
-                let imgse = Imgse::new(username, password, *timeout, extra.as_ref());
+                let imgse = Imgse::new(username, password, *timeout, extra.as_ref(), proxy)?;
                 Box::new(imgse)
             }
             _ => unreachable!(),
@@ -138,7 +255,7 @@ pub fn use_manager(
                 timeout,
                 extra,
             } => {
-                let imgtg = Imgtg::new(username, password, *timeout, extra.as_ref());
+                let imgtg = Imgtg::new(username, password, *timeout, extra.as_ref(), proxy)?;
                 Box::new(imgtg)
             }
             _ => unreachable!(),
@@ -149,36 +266,137 @@ pub fn use_manager(
                 username,
                 repository,
                 path,
+                branch,
+                name_template,
                 ..
             } => {
-                let authorization = format!("Bearer {}", token);
-
                 let github = GitManager::new(
+                    GitProvider::Github,
                     "github",
-                    "https://api.github.com",
+                    GitRepository {
+                        base_url: "https://api.github.com",
+                        token,
+                        username,
+                        repository,
+                        path: path.as_deref(),
+                        branch: branch.as_deref(),
+                        name_template: name_template.as_deref(),
+                    },
                     HashMap::from([
                         (
                             "Accept".to_owned(),
                             "application/vnd.github+json".to_owned(),
                         ),
                         ("User-Agent".to_owned(), "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36".to_owned()),
-                        ("Authorization".to_owned(), authorization),
                         ("X-GitHub-Api-Version".to_owned(), "2022-11-28".to_owned()),
                     ]),
-                    &token,
-                    &username,
-                    &repository,
-                    path.as_deref(),
                     Some(180),
                     20,
-                );
+                    proxy,
+                )?;
                 Box::new(github)
             }
             _ => unreachable!(),
         },
+        ManagerCode::Gitlab | ManagerCode::Gitea | ManagerCode::Forgejo => match auth_config {
+            ManagerAuthConfigKind::Git {
+                base_url,
+                token,
+                username,
+                repository,
+                path,
+                branch,
+                name_template,
+            } => {
+                let (provider, base_url) = match using {
+                    ManagerCode::Gitlab if base_url.is_empty() => {
+                        (GitProvider::Gitlab, "https://gitlab.com")
+                    }
+                    ManagerCode::Gitlab => (GitProvider::Gitlab, base_url.as_str()),
+                    // gitea 和 forgejo 只能自托管，必须配置 base_url
+                    _ if base_url.is_empty() => {
+                        return Err(Up2bError::Config(ConfigError::IsNull(
+                            "base_url".to_owned(),
+                        )))
+                    }
+                    _ => (GitProvider::Gitea, base_url.as_str()),
+                };
+
+                let manager = GitManager::new(
+                    provider,
+                    &using.name(),
+                    GitRepository {
+                        base_url,
+                        token,
+                        username,
+                        repository,
+                        path: path.as_deref(),
+                        branch: branch.as_deref(),
+                        name_template: name_template.as_deref(),
+                    },
+                    HashMap::from([("Accept".to_owned(), "application/json".to_owned())]),
+                    Some(180),
+                    20,
+                    proxy,
+                )?;
+                Box::new(manager)
+            }
+            _ => return Err(Up2bError::Config(ConfigError::Type(using.name()))),
+        },
+        ManagerCode::S3 => match auth_config {
+            ManagerAuthConfigKind::S3 {
+                endpoint,
+                region,
+                bucket,
+                access_key,
+                secret_key,
+                prefix,
+                path_style,
+                public_url,
+                name_template,
+            } => {
+                let manager = S3Manager::new(
+                    &using.name(),
+                    S3Bucket {
+                        endpoint,
+                        region,
+                        bucket,
+                        access_key,
+                        secret_key,
+                        prefix: prefix.as_deref(),
+                        path_style: *path_style,
+                        public_url: public_url.as_deref(),
+                    },
+                    name_template.as_deref(),
+                    proxy,
+                )?;
+                Box::new(manager)
+            }
+            _ => return Err(Up2bError::Config(ConfigError::Type(using.name()))),
+        },
+        ManagerCode::Local => match auth_config {
+            ManagerAuthConfigKind::Local {
+                directory,
+                url_prefix,
+                name_template,
+            } => {
+                let manager = LocalManager::new(
+                    &using.name(),
+                    directory,
+                    url_prefix,
+                    name_template.as_deref(),
+                )?;
+                Box::new(manager)
+            }
+            _ => return Err(Up2bError::Config(ConfigError::Type(using.name()))),
+        },
         ManagerCode::Custom(s) => match auth_config {
-            ManagerAuthConfigKind::API { token, api } => {
-                let manager = BaseManager::new(
+            ManagerAuthConfigKind::Api {
+                token,
+                api,
+                name_template,
+            } => {
+                let mut manager = BaseManager::new(
                     s.as_str(),
                     api.base_url(),
                     api.max_size(),
@@ -186,7 +404,10 @@ pub fn use_manager(
                     api.timeout().into(),
                     #[cfg(feature = "compress")]
                     api.compressed_format().clone(),
-                );
+                    proxy,
+                )?;
+                manager.name_template = name_template.clone();
+
                 let custom = BaseApiManager::new(manager, token, api);
 
                 Box::new(custom)
@@ -198,6 +419,17 @@ pub fn use_manager(
     Ok(uploader)
 }
 
+/// 服务端错误和限流时返回带状态码的错误，需要在解析响应体之前调用，
+/// 否则错误会变成反序列化错误，无法换一个图床重试
+pub(crate) fn check_server_error(status: StatusCode) -> Up2bResult<()> {
+    if is_retryable_status(status) {
+        error!("服务端错误，状态码：{}", status);
+        return Err(Up2bError::Status(status));
+    }
+
+    Ok(())
+}
+
 async fn is_exceeded(
     image_bed_name: &str,
     image_path: &Path,
@@ -212,38 +444,48 @@ async fn is_exceeded(
         "image size exceeds the maximum limit: {} > {}",
         file_size, max_size
     );
-    return Err(Up2bError::OverSize(
+    Err(Up2bError::OverSize(
         image_bed_name.to_string(),
         image_path.to_string_lossy().to_string(),
-        max_size / 1024 / 1024,
-        file_size / 1024 / 1024,
-    ));
+        max_size,
+        file_size,
+    ))
 }
 
 enum RequestWithBodyMethod {
-    PUT,
-    POST,
+    Put,
+    Post,
 }
 
 impl RequestWithBodyMethod {
     fn as_method(&self) -> Method {
         match self {
-            RequestWithBodyMethod::PUT => Method::PUT,
-            RequestWithBodyMethod::POST => Method::POST,
+            RequestWithBodyMethod::Put => Method::PUT,
+            RequestWithBodyMethod::Post => Method::POST,
         }
     }
 }
 
+/// 上传图片的请求方法、地址和请求头
+struct UploadRequest<'a> {
+    method: RequestWithBodyMethod,
+    url: &'a str,
+    headers: HeaderMap,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct BaseManager {
     name: String,
     max_size: u8,
     base_url: String,
     client: Client,
+    proxy: Option<ProxyKind>,
     allowed_formats: Vec<AllowedImageFormat>,
     #[cfg(feature = "compress")]
     compressed_format: CompressedFormat,
     timeout: Duration,
+    /// 上传后的文件名模板
+    name_template: Option<String>,
 }
 
 impl BaseManager {
@@ -254,17 +496,33 @@ impl BaseManager {
         allowed_formats: A,
         timeout: Option<u8>,
         #[cfg(feature = "compress")] compressed_format: CompressedFormat,
-    ) -> Self {
-        Self {
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let client = client_builder(proxy)?.build()?;
+
+        Ok(Self {
             name: name.into(),
             base_url: base_url.into(),
             max_size,
-            client: Client::new(),
+            client,
+            proxy: proxy.cloned(),
             allowed_formats: allowed_formats.into(),
             timeout: Duration::from_secs(timeout.unwrap_or(5).into()),
             #[cfg(feature = "compress")]
             compressed_format,
-        }
+            name_template: None,
+        })
+    }
+
+    /// 按文件名模板生成上传后的文件名，未设置模板时使用默认模板
+    async fn file_name(&self, image_path: &Path) -> Up2bResult<String> {
+        render_file_name(
+            self.name_template
+                .as_deref()
+                .unwrap_or(DEFAULT_NAME_TEMPLATE),
+            image_path,
+        )
+        .await
     }
 
     fn url(&self, path: &str) -> String {
@@ -320,13 +578,29 @@ impl BaseManager {
             .timeout(self.timeout)
     }
 
+    /// 压缩后的格式，只从图床允许的格式中选择，优先使用配置的格式
+    #[cfg(feature = "compress")]
+    fn compressed_format(&self) -> Option<CompressedFormat> {
+        [
+            self.compressed_format.clone(),
+            CompressedFormat::Webp,
+            CompressedFormat::Jpeg,
+            CompressedFormat::Avif,
+            CompressedFormat::Png,
+        ]
+        .into_iter()
+        .find(|f| self.allowed_formats.contains(&f.image_format()))
+    }
+
+    /// 返回需要上传的文件和它的路径，压缩后路径的扩展名与压缩格式一致，
+    /// 生成上传后的文件名和 mime 类型时应使用此路径
     async fn compress(
         &self,
-        #[cfg(feature = "compress")] window: Option<&Window>,
+        #[cfg(feature = "compress")] window: Option<&WebviewWindow>,
         file: File,
         image_path: &Path,
         #[cfg(feature = "compress")] filename: &str,
-    ) -> Up2bResult<File> {
+    ) -> Up2bResult<(File, PathBuf)> {
         let file_size = file.metadata().await?.len();
 
         let max_size = u64::from(self.max_size) * 1024 * 1024;
@@ -334,6 +608,9 @@ impl BaseManager {
         #[cfg(not(feature = "compress"))]
         is_exceeded(&self.name, image_path, max_size, file_size).await?;
 
+        #[cfg(not(feature = "compress"))]
+        let file = (file, image_path.to_owned());
+
         #[cfg(feature = "compress")]
         let file = {
             let config = CONFIG.read().await.clone().unwrap();
@@ -341,13 +618,30 @@ impl BaseManager {
                 is_exceeded(&self.name, image_path, max_size, file_size).await?;
             }
 
+            let format = match self.compressed_format() {
+                Some(f) => f,
+                None => {
+                    warn!("图床不支持任何可压缩的格式：{}", self.name);
+                    is_exceeded(&self.name, image_path, max_size, file_size).await?;
+                    return Ok((file, image_path.to_owned()));
+                }
+            };
+
+            let target = CompressTarget {
+                image_bed_name: &self.name,
+                max_size,
+                margin: config.compression_margin(),
+                format: &format,
+            };
+
             compress(
                 window,
-                max_size,
+                &target,
                 file_size,
                 filename,
+                image_path,
                 file,
-                &self.compressed_format,
+                &config.decode_limits(),
             )
             .await?
         };
@@ -358,40 +652,27 @@ impl BaseManager {
     async fn upload_json<T: Serialize>(
         &self,
         window: Option<WebviewWindow>,
-        method: RequestWithBodyMethod,
         id: u32,
-        url: &str,
-        header: HeaderMap,
+        request: UploadRequest<'_>,
         key: &str,
         image_path: &Path,
         form: Option<T>,
     ) -> Up2bResult<Response> {
-        // TODO: base64 上传对体积的限制待处理
-        let file_data = read(image_path).await?;
-        let file_data = general_purpose::STANDARD.encode(file_data);
+        let mut form = match serde_json::json!(form) {
+            Value::Null => Map::new(),
+            Value::Object(map) => map,
+            _ => return Err(Up2bError::Other("错误的类型".to_owned())),
+        };
 
-        let mut body = serde_json::json!(form);
+        debug!("除图片外的请求体：{:?}", form);
 
-        debug!("序列化后除图片外的请求体：{}", body);
+        form.remove(key);
 
-        match body {
-            serde_json::Value::Null => {
-                let mut map = Map::new();
-                map.insert(key.to_owned(), Value::String(file_data));
+        let file = File::open(image_path).await?;
 
-                body = Value::Object(map);
-            }
-            serde_json::Value::Object(mut map) => {
-                map.insert(key.to_owned(), Value::String(file_data));
+        let builder = self.request(request.method.as_method(), request.url, request.headers);
 
-                body = Value::Object(map);
-            }
-            _ => return Err(Up2bError::Other("错误的类型".to_owned())),
-        };
-
-        let builder = self.request(method.as_method(), url, header);
-
-        let resp = json::upload(builder, window.as_ref(), body, id).await?;
+        let resp = json::upload(builder, window.as_ref(), id, form, key, file).await?;
 
         Ok(resp)
     }
@@ -400,17 +681,17 @@ impl BaseManager {
         &self,
         window: Option<WebviewWindow>,
         id: u32,
-        url: &str,
-        header: HeaderMap,
+        request: UploadRequest<'_>,
         image_path: &Path,
-        file_part_name: &str,
-        file_kind: &FileKind,
+        file_part: FilePart<'_>,
         form: Option<&[(&str, &str)]>,
     ) -> Up2bResult<Response> {
         let file = File::open(&image_path).await?;
+
+        #[cfg(feature = "compress")]
         let filename = image_path.file_name().unwrap().to_str().unwrap();
 
-        let file = self
+        let (file, upload_path) = self
             .compress(
                 #[cfg(feature = "compress")]
                 window.as_ref(),
@@ -421,46 +702,74 @@ impl BaseManager {
             )
             .await?;
 
-        let mime_type = guess_mime_type_by_ext(image_path.extension().unwrap().to_str().unwrap());
+        // 压缩后扩展名可能改变，文件名和 mime 类型都使用实际上传的文件
+        let filename = upload_path.file_name().unwrap().to_str().unwrap();
+
+        // 表单上传只能指定文件名，模板中的目录会被忽略
+        let rendered = match &self.name_template {
+            Some(t) => Some(render_file_name(t, &upload_path).await?),
+            None => None,
+        };
+        let upload_filename = rendered
+            .as_deref()
+            .and_then(|n| n.rsplit('/').next())
+            .unwrap_or(filename);
+
+        let mime_type = guess_mime_type_by_ext(upload_path.extension().unwrap().to_str().unwrap());
 
         debug!("guess mime type: {}", mime_type);
 
-        let request_builder = self.request(Method::POST, url, header);
+        let request_builder =
+            self.request(request.method.as_method(), request.url, request.headers);
 
         let response = multipart::upload(
             request_builder,
             window.as_ref(),
             id,
-            file_part_name,
-            filename,
-            UploadFile::new(file, file_kind),
-            &mime_type,
+            UploadFile::new(file, file_part, upload_filename, &mime_type),
             form,
         )
         .await
         .map_err(|e| {
             error!("上传图片出错：{}", e);
             e
-        })?;
+        });
+
+        remove_compressed(image_path, &upload_path).await;
+
+        response
+    }
+}
 
-        Ok(response)
+/// 上传结束后删除压缩生成的临时文件，未压缩时 upload_path 就是原图
+pub(crate) async fn remove_compressed(image_path: &Path, upload_path: &Path) {
+    if upload_path != image_path {
+        remove_temp_image(upload_path).await;
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ManagerKind {
-    API,
+    Api,
     Git,
     Chevereto,
+    S3,
+    Local,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub enum ManagerCode {
+    #[default]
     Smms, // 内置 smms 支持，与 Custom
     Imgse,
     Imgtg,
     Github,
+    Gitlab,
+    Gitea,
+    Forgejo,
+    S3,
+    Local,
     Custom(String),
 }
 
@@ -474,6 +783,11 @@ impl Serialize for ManagerCode {
             ManagerCode::Imgse => serializer.serialize_str("IMGSE"),
             ManagerCode::Imgtg => serializer.serialize_str("IMGTG"),
             ManagerCode::Github => serializer.serialize_str("GITHUB"),
+            ManagerCode::Gitlab => serializer.serialize_str("GITLAB"),
+            ManagerCode::Gitea => serializer.serialize_str("GITEA"),
+            ManagerCode::Forgejo => serializer.serialize_str("FORGEJO"),
+            ManagerCode::S3 => serializer.serialize_str("S3"),
+            ManagerCode::Local => serializer.serialize_str("LOCAL"),
             ManagerCode::Custom(s) => {
                 serializer.serialize_str(&format!("CUSTOM-{}", s.to_uppercase()))
             }
@@ -510,9 +824,25 @@ impl<'de> Deserialize<'de> for ManagerCode {
                         "IMGSE" => Ok(ManagerCode::Imgse),
                         "IMGTG" => Ok(ManagerCode::Imgtg),
                         "GITHUB" => Ok(ManagerCode::Github),
+                        "GITLAB" => Ok(ManagerCode::Gitlab),
+                        "GITEA" => Ok(ManagerCode::Gitea),
+                        "FORGEJO" => Ok(ManagerCode::Forgejo),
+                        "S3" => Ok(ManagerCode::S3),
+                        "LOCAL" => Ok(ManagerCode::Local),
                         _ => Err(serde::de::Error::unknown_variant(
                             value,
-                            &["SMMS", "IMGSE", "IMGTG", "GITHUB", "CUSTOM-{}"],
+                            &[
+                                "SMMS",
+                                "IMGSE",
+                                "IMGTG",
+                                "GITHUB",
+                                "GITLAB",
+                                "GITEA",
+                                "FORGEJO",
+                                "S3",
+                                "LOCAL",
+                                "CUSTOM-{}",
+                            ],
                         )),
                     }
                 }
@@ -529,12 +859,6 @@ impl Display for ManagerCode {
     }
 }
 
-impl Default for ManagerCode {
-    fn default() -> Self {
-        ManagerCode::Smms
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ManagerItem {
     pub key: ManagerCode,
@@ -550,6 +874,11 @@ impl ManagerCode {
             ManagerCode::Imgse => "imgse.com".to_owned(),
             ManagerCode::Imgtg => "imgtg.com".to_owned(),
             ManagerCode::Github => "github.com".to_owned(),
+            ManagerCode::Gitlab => "gitlab".to_owned(),
+            ManagerCode::Gitea => "gitea".to_owned(),
+            ManagerCode::Forgejo => "forgejo".to_owned(),
+            ManagerCode::S3 => "s3".to_owned(),
+            ManagerCode::Local => "local".to_owned(),
             ManagerCode::Custom(s) => "CUSTOM-".to_owned() + s,
         }
     }
@@ -560,17 +889,20 @@ impl ManagerCode {
             ManagerCode::Imgse => Some("https://imgse.com"),
             ManagerCode::Imgtg => Some("https://imgtg.com"),
             ManagerCode::Github => Some("https://github.com"),
+            ManagerCode::Gitlab => Some("https://gitlab.com"),
+            ManagerCode::Gitea => Some("https://about.gitea.com"),
+            ManagerCode::Forgejo => Some("https://forgejo.org"),
             _ => None,
         }
     }
 
-    pub fn to_manager_item(self) -> ManagerItem {
+    pub fn into_manager_item(self) -> ManagerItem {
         match self {
             ManagerCode::Smms => ManagerItem {
                 name: self.name(),
                 index: self.index(),
                 key: self,
-                r#type: ManagerKind::API,
+                r#type: ManagerKind::Api,
             },
             ManagerCode::Imgse => ManagerItem {
                 name: self.name(),
@@ -584,27 +916,47 @@ impl ManagerCode {
                 key: self,
                 r#type: ManagerKind::Chevereto,
             },
-            ManagerCode::Github => ManagerItem {
+            ManagerCode::Github
+            | ManagerCode::Gitlab
+            | ManagerCode::Gitea
+            | ManagerCode::Forgejo => ManagerItem {
                 name: self.name(),
                 index: self.index(),
                 key: self,
                 r#type: ManagerKind::Git,
             },
+            ManagerCode::S3 => ManagerItem {
+                name: self.name(),
+                index: self.index(),
+                key: self,
+                r#type: ManagerKind::S3,
+            },
+            ManagerCode::Local => ManagerItem {
+                name: self.name(),
+                index: self.index(),
+                key: self,
+                r#type: ManagerKind::Local,
+            },
             ManagerCode::Custom(ref s) => ManagerItem {
                 name: s.clone(),
                 index: None,
                 key: self,
-                r#type: ManagerKind::API,
+                r#type: ManagerKind::Api,
             },
         }
     }
 }
 
 lazy_static! {
-    pub(crate) static ref MANAGERS: [ManagerItem; 4] = [
-        ManagerCode::Smms.to_manager_item(),
-        ManagerCode::Imgse.to_manager_item(),
-        ManagerCode::Imgtg.to_manager_item(),
-        ManagerCode::Github.to_manager_item()
+    pub(crate) static ref MANAGERS: [ManagerItem; 9] = [
+        ManagerCode::Smms.into_manager_item(),
+        ManagerCode::Imgse.into_manager_item(),
+        ManagerCode::Imgtg.into_manager_item(),
+        ManagerCode::Github.into_manager_item(),
+        ManagerCode::Gitlab.into_manager_item(),
+        ManagerCode::Gitea.into_manager_item(),
+        ManagerCode::Forgejo.into_manager_item(),
+        ManagerCode::S3.into_manager_item(),
+        ManagerCode::Local.into_manager_item()
     ];
 }
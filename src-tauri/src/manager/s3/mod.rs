@@ -0,0 +1,667 @@
+mod sign;
+
+use std::{
+    io::SeekFrom,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Method, RequestBuilder, Response, StatusCode, Url,
+};
+use tauri::WebviewWindow;
+use time::OffsetDateTime;
+use tokio::io::AsyncSeekExt;
+
+#[cfg(feature = "compress")]
+use super::CompressedFormat;
+use super::{
+    check_server_error, remove_compressed, AllowedImageFormat, BaseManager, DeleteError,
+    DeleteResponse, Extra, ImageItem, Manage, UploadResult,
+};
+
+use crate::{
+    config::ProxyKind,
+    error::{PathError, S3Error, TokenError},
+    http::multipart::file_to_body,
+    util::{hash::sha256, image::guess_mime_type_by_ext, url::percent_encode},
+    Up2bError, Up2bResult,
+};
+
+use self::sign::{authorization, Credentials, SignRequest, EMPTY_PAYLOAD_HASH};
+
+/// ListObjectsV2 每次请求的最大数量
+const MAX_KEYS: u16 = 1000;
+
+lazy_static! {
+    static ref KEY_RE: Regex = Regex::new(r"<Key>(.*?)</Key>").unwrap();
+    static ref CONTINUATION_TOKEN_RE: Regex =
+        Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap();
+    static ref ERROR_CODE_RE: Regex = Regex::new(r"<Code>(.*?)</Code>").unwrap();
+    static ref ERROR_MESSAGE_RE: Regex = Regex::new(r"<Message>(.*?)</Message>").unwrap();
+}
+
+/// 还原 xml 中的转义字符
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// s3 请求签名所需的时间，格式为 20130524T000000Z
+fn amz_date() -> String {
+    let now = OffsetDateTime::now_utc();
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+/// 存储桶配置，对应 ManagerAuthConfigKind::S3
+pub(super) struct S3Bucket<'a> {
+    pub endpoint: &'a str,
+    pub region: &'a str,
+    pub bucket: &'a str,
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub prefix: Option<&'a str>,
+    pub path_style: bool,
+    pub public_url: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub(super) struct S3Manager {
+    /// base_url 为 endpoint
+    inner: BaseManager,
+    region: String,
+    bucket: String,
+    access_key: String,
+    secret_key: String,
+    /// 对象键前缀，不以 `/` 开头或结尾
+    prefix: String,
+    /// 为 true 时使用 `{endpoint}/{bucket}` 形式的地址，MinIO 等自建服务通常只支持这种形式
+    path_style: bool,
+    /// 图片的公开访问地址，比如 CDN 或自定义域名，为 None 时使用对象地址
+    public_url: Option<String>,
+}
+
+impl S3Manager {
+    pub(super) fn new(
+        name: &str,
+        config: S3Bucket,
+        name_template: Option<&str>,
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let S3Bucket {
+            endpoint,
+            region,
+            bucket,
+            access_key,
+            secret_key,
+            prefix,
+            path_style,
+            public_url,
+        } = config;
+
+        let mut inner = BaseManager::new(
+            name,
+            endpoint.trim_end_matches('/'),
+            100,
+            vec![
+                AllowedImageFormat::Jpeg,
+                AllowedImageFormat::Png,
+                AllowedImageFormat::Gif,
+                AllowedImageFormat::Bmp,
+                AllowedImageFormat::Webp,
+                AllowedImageFormat::Avif,
+            ],
+            Some(60),
+            #[cfg(feature = "compress")]
+            CompressedFormat::Webp,
+            proxy,
+        )?;
+        inner.name_template = name_template.map(|t| t.to_owned());
+
+        Ok(Self {
+            inner,
+            region: region.to_owned(),
+            bucket: bucket.to_owned(),
+            access_key: access_key.to_owned(),
+            secret_key: secret_key.to_owned(),
+            prefix: prefix.unwrap_or_default().trim_matches('/').to_owned(),
+            path_style,
+            public_url: public_url.map(|u| u.trim_end_matches('/').to_owned()),
+        })
+    }
+
+    fn bucket_url(&self) -> Up2bResult<String> {
+        if self.path_style {
+            return Ok(format!("{}/{}", self.inner.base_url, self.bucket));
+        }
+
+        let endpoint = Url::parse(&self.inner.base_url)
+            .map_err(|e| Up2bError::Other(format!("endpoint 无效：{}", e)))?;
+
+        let host = match endpoint.host_str() {
+            Some(h) => h,
+            None => return Err(Up2bError::Other("endpoint 中没有主机名".to_owned())),
+        };
+
+        Ok(match endpoint.port() {
+            None => format!("{}://{}.{}", endpoint.scheme(), self.bucket, host),
+            Some(port) => format!("{}://{}.{}:{}", endpoint.scheme(), self.bucket, host, port),
+        })
+    }
+
+    fn object_url(&self, key: &str) -> Up2bResult<String> {
+        Ok(format!(
+            "{}/{}",
+            self.bucket_url()?,
+            percent_encode(key, true)
+        ))
+    }
+
+    fn public_object_url(&self, key: &str) -> Up2bResult<String> {
+        match &self.public_url {
+            None => self.object_url(key),
+            Some(u) => Ok(format!("{}/{}", u, percent_encode(key, true))),
+        }
+    }
+
+    /// 创建带有 v4 签名的请求
+    fn signed_request(
+        &self,
+        method: Method,
+        url: &str,
+        payload_hash: &str,
+        mut headers: HeaderMap,
+    ) -> Up2bResult<RequestBuilder> {
+        let parsed_url =
+            Url::parse(url).map_err(|e| Up2bError::Other(format!("请求地址无效：{}", e)))?;
+
+        let host = match (parsed_url.host_str(), parsed_url.port()) {
+            (None, _) => return Err(Up2bError::Other("请求地址中没有主机名".to_owned())),
+            (Some(h), None) => h.to_owned(),
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+        };
+
+        let amz_date = amz_date();
+
+        let authorization = authorization(
+            &Credentials {
+                access_key: &self.access_key,
+                secret_key: &self.secret_key,
+                region: &self.region,
+            },
+            &SignRequest {
+                method: method.as_str(),
+                host: &host,
+                path: parsed_url.path(),
+                query: parsed_url.query(),
+                payload_hash,
+                amz_date: &amz_date,
+            },
+        );
+
+        headers.insert("x-amz-date", amz_date.parse().unwrap());
+        headers.insert("x-amz-content-sha256", payload_hash.parse().unwrap());
+        headers.insert(
+            "Authorization",
+            authorization
+                .parse()
+                .map_err(|_| Up2bError::Other("签名中有无效字符".to_owned()))?,
+        );
+
+        Ok(self.inner.request(method, url, headers))
+    }
+
+    /// 解析 s3 的 xml 错误响应
+    async fn parse_error(&self, response: Response) -> Up2bError {
+        let status = response.status();
+
+        let text = match response.text().await {
+            Ok(t) => t,
+            Err(e) => return e.into(),
+        };
+
+        debug!("s3 错误响应：status={}, body={}", status, text);
+
+        // SlowDown、InternalError 等错误可以换一个图床重试
+        if let Err(e) = check_server_error(status) {
+            return e;
+        }
+
+        let code = ERROR_CODE_RE
+            .captures(&text)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().to_owned());
+
+        match code {
+            None => Up2bError::Status(status),
+            Some(code) => {
+                let message = ERROR_MESSAGE_RE
+                    .captures(&text)
+                    .and_then(|c| c.get(1))
+                    .map(|m| unescape_xml(m.as_str()))
+                    .unwrap_or_default();
+
+                error!("s3 请求失败：code={}, message={}", code, message);
+
+                S3Error::Response { code, message }.into()
+            }
+        }
+    }
+
+    async fn list_page(
+        &self,
+        continuation_token: Option<&str>,
+        max_keys: u16,
+    ) -> Up2bResult<(Vec<String>, Option<String>)> {
+        let prefix = if self.prefix.is_empty() {
+            String::new()
+        } else {
+            self.prefix.clone() + "/"
+        };
+
+        let mut url = format!(
+            "{}?list-type=2&max-keys={}&prefix={}",
+            self.bucket_url()?,
+            max_keys,
+            percent_encode(&prefix, false)
+        );
+
+        if let Some(token) = continuation_token {
+            url += &format!("&continuation-token={}", percent_encode(token, false));
+        }
+
+        let resp = self
+            .signed_request(Method::GET, &url, EMPTY_PAYLOAD_HASH, HeaderMap::new())?
+            .send()
+            .await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(self.parse_error(resp).await);
+        }
+
+        let text = resp.text().await?;
+
+        let keys = KEY_RE
+            .captures_iter(&text)
+            .map(|c| unescape_xml(&c[1]))
+            .collect();
+
+        let next = CONTINUATION_TOKEN_RE
+            .captures(&text)
+            .map(|c| unescape_xml(&c[1]));
+
+        Ok((keys, next))
+    }
+
+    pub async fn list(&self) -> Up2bResult<Vec<ImageItem>> {
+        let mut images = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let (keys, next) = self
+                .list_page(continuation_token.as_deref(), MAX_KEYS)
+                .await?;
+
+            for key in keys.into_iter() {
+                // 跳过“目录”
+                if key.ends_with('/') {
+                    continue;
+                }
+
+                images.push(ImageItem {
+                    url: self.public_object_url(&key)?,
+                    deleted_id: key,
+                    thumb: None,
+                    manager: None,
+                });
+            }
+
+            match next {
+                None => break,
+                Some(t) => continuation_token = Some(t),
+            }
+        }
+
+        debug!("图片列表：{:?}", images);
+
+        Ok(images)
+    }
+
+    pub async fn delete(&self, key: &str) -> Up2bResult<()> {
+        let url = self.object_url(key)?;
+
+        let resp = self
+            .signed_request(Method::DELETE, &url, EMPTY_PAYLOAD_HASH, HeaderMap::new())?
+            .send()
+            .await?;
+
+        match resp.status() {
+            StatusCode::NO_CONTENT | StatusCode::OK => {
+                info!("已删除对象：{}", key);
+                Ok(())
+            }
+            _ => Err(self.parse_error(resp).await),
+        }
+    }
+
+    async fn upload(
+        &self,
+        window: Option<WebviewWindow>,
+        id: u32,
+        image_path: &Path,
+    ) -> Up2bResult<UploadResult> {
+        let filename = match image_path.file_name() {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => return Err(PathError::NotFile.into()),
+        };
+
+        if !filename.contains('.') {
+            return Err(PathError::NotFile.into());
+        }
+
+        let file = tokio::fs::File::open(image_path).await?;
+        let (mut file, upload_path) = self
+            .inner
+            .compress(
+                #[cfg(feature = "compress")]
+                window.as_ref(),
+                file,
+                image_path,
+                #[cfg(feature = "compress")]
+                &filename,
+            )
+            .await?;
+
+        // 压缩后扩展名可能改变，对象名和 mime 类型都使用实际上传的文件
+        let name = self.inner.file_name(&upload_path).await?;
+        let key = if self.prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", self.prefix, name)
+        };
+
+        // 先计算哈希再从头读取上传，不将整个文件读入内存
+        let (payload_hash, size) = sha256(&mut file).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+
+        let ext = upload_path
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, guess_mime_type_by_ext(&ext).parse().unwrap());
+
+        let url = self.object_url(&key)?;
+        let builder = self.signed_request(Method::PUT, &url, &payload_hash, headers)?;
+
+        let body = match window {
+            None => Body::from(file),
+            Some(w) => file_to_body(id, Arc::new(Mutex::new(w)), file).await?,
+        };
+
+        let builder = builder.header(CONTENT_LENGTH, size).body(body);
+
+        let resp = builder.send().await;
+
+        remove_compressed(image_path, &upload_path).await;
+
+        let resp = resp?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(self.parse_error(resp).await);
+        }
+
+        let url = self.public_object_url(&key)?;
+
+        info!("图片已上传：path={:?}, url={}", image_path, url);
+
+        Ok(UploadResult::Response(ImageItem {
+            url,
+            deleted_id: key,
+            thumb: None,
+            manager: None,
+        }))
+    }
+}
+
+#[async_trait]
+impl Manage for S3Manager {
+    fn allowed_formats(&self) -> Vec<AllowedImageFormat> {
+        self.inner.allowed_formats.clone()
+    }
+
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.compressed_format = format;
+    }
+
+    fn support_stream(&self) -> bool {
+        true
+    }
+
+    async fn verify(&self) -> Up2bResult<Option<Extra>> {
+        // 列出一个对象即可验证密钥和存储桶是否可用
+        if let Err(e) = self.list_page(None, 1).await {
+            return match e {
+                Up2bError::S3(S3Error::Response { code, message })
+                    if code == "InvalidAccessKeyId" || code == "SignatureDoesNotMatch" =>
+                {
+                    Err(TokenError::Invalid(message).into())
+                }
+                Up2bError::S3(S3Error::Response { code, .. }) if code == "AccessDenied" => {
+                    Err(TokenError::NoPermission(self.bucket.clone()).into())
+                }
+                e => Err(e),
+            };
+        }
+
+        Ok(Some(Extra::from([
+            ("bucket".to_owned(), self.bucket.clone()),
+            ("region".to_owned(), self.region.clone()),
+        ])))
+    }
+
+    async fn get_all_images(&self) -> Up2bResult<Vec<ImageItem>> {
+        self.list().await
+    }
+
+    async fn delete_image(&self, id: &str) -> Up2bResult<DeleteResponse> {
+        match self.delete(id).await {
+            Ok(()) => Ok(DeleteResponse {
+                success: true,
+                error: None,
+            }),
+            Err(e) => Ok(DeleteResponse {
+                success: false,
+                error: Some(DeleteError::Other(e.to_string())),
+            }),
+        }
+    }
+
+    async fn upload_image(
+        &self,
+        window: Option<WebviewWindow>,
+        id: u32,
+        image_path: &Path,
+    ) -> UploadResult {
+        match self.upload(window, id, image_path).await {
+            Ok(r) => r,
+            Err(e) => {
+                return UploadResult::Error {
+                    code: e.as_string(),
+                    detail: e,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use axum::{
+        body::Bytes,
+        http::{
+            HeaderMap as AxumHeaderMap, Method as AxumMethod, StatusCode as AxumStatusCode, Uri,
+        },
+        Router,
+    };
+
+    use super::*;
+    use crate::http::mock;
+
+    fn manager(endpoint: &str) -> S3Manager {
+        S3Manager::new(
+            "s3",
+            S3Bucket {
+                endpoint,
+                region: "us-east-1",
+                bucket: "bucket",
+                access_key: "AKIDEXAMPLE",
+                secret_key: "secret",
+                prefix: None,
+                path_style: true,
+                public_url: None,
+            },
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    async fn upload_with_status(status: u16, body: &'static str) -> Up2bError {
+        mock::init_config().await;
+
+        let status = AxumStatusCode::from_u16(status).unwrap();
+        let endpoint =
+            mock::serve(Router::new().fallback(move |_: Bytes| async move { (status, body) }))
+                .await;
+
+        let image_path = mock::temp_file("a.png", b"png").await;
+        manager(&endpoint)
+            .upload(None, 0, &image_path)
+            .await
+            .err()
+            .unwrap()
+    }
+
+    struct Received {
+        method: String,
+        path: String,
+        headers: AxumHeaderMap,
+        body: Bytes,
+    }
+
+    #[tokio::test]
+    async fn put_and_delete_are_signed() {
+        mock::init_config().await;
+
+        let received = Arc::new(StdMutex::new(Vec::new()));
+        let record = received.clone();
+        let endpoint = mock::serve(Router::new().fallback(
+            move |method: AxumMethod, uri: Uri, headers: AxumHeaderMap, body: Bytes| {
+                let record = record.clone();
+                async move {
+                    let status = if method == AxumMethod::DELETE {
+                        AxumStatusCode::NO_CONTENT
+                    } else {
+                        AxumStatusCode::OK
+                    };
+                    record.lock().unwrap().push(Received {
+                        method: method.to_string(),
+                        path: uri.path().to_owned(),
+                        headers,
+                        body,
+                    });
+                    status
+                }
+            },
+        ))
+        .await;
+
+        let manager = manager(&endpoint);
+        let image_path = mock::temp_file("a.png", b"png").await;
+        let item = match manager.upload(None, 0, &image_path).await.unwrap() {
+            UploadResult::Response(item) => item,
+            _ => unreachable!(),
+        };
+        manager.delete(&item.deleted_id).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let host = endpoint.trim_start_matches("http://");
+        let object_path = format!("/bucket/{}", item.deleted_id);
+
+        assert_eq!(received.len(), 2);
+        for (r, method) in received.iter().zip(["PUT", "DELETE"]) {
+            assert_eq!(r.method, method);
+            assert_eq!(r.path, object_path);
+
+            let header = |name: &str| r.headers.get(name).unwrap().to_str().unwrap();
+            let expected = authorization(
+                &Credentials {
+                    access_key: "AKIDEXAMPLE",
+                    secret_key: "secret",
+                    region: "us-east-1",
+                },
+                &SignRequest {
+                    method,
+                    host,
+                    path: &r.path,
+                    query: None,
+                    payload_hash: header("x-amz-content-sha256"),
+                    amz_date: header("x-amz-date"),
+                },
+            );
+            assert_eq!(header("authorization"), expected);
+        }
+
+        let put = &received[0];
+        assert_eq!(&put.body[..], b"png");
+        assert_eq!(
+            put.headers.get("x-amz-content-sha256").unwrap(),
+            "8f8cbb7dcf46e0bc7d53265749a6c17d116093a6ba95e442764060c76fd4a86c"
+        );
+        assert_eq!(
+            received[1].headers.get("x-amz-content-sha256").unwrap(),
+            EMPTY_PAYLOAD_HASH
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_down_is_retryable() {
+        let e = upload_with_status(
+            503,
+            "<Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message></Error>",
+        )
+        .await;
+        assert!(e.is_retryable(), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn access_denied_is_not_retryable() {
+        let e = upload_with_status(
+            403,
+            "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>",
+        )
+        .await;
+        assert!(matches!(e, Up2bError::S3(_)), "{e:?}");
+        assert!(!e.is_retryable(), "{e:?}");
+    }
+}
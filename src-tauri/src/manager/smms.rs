@@ -1,16 +1,18 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 use async_trait::async_trait;
-use serde_json::Value;
+use serde_json::{Map, Value};
 use tauri::WebviewWindow;
 
+use crate::config::ProxyKind;
 use crate::Up2bResult;
 use crate::{http::multipart::FileKind, manager::api::UploadResponseStatus};
 
 use super::api::{
     Api, AuthMethod, BaseApiManager, Delete, DeleteKeyKind, DeleteMethod, List, ListRequestMethod,
     ListResponseController, Upload, UploadResponseController, UploadResponseErrorController,
-    UploadResponseSuccuessController,
+    UploadResponseSuccuessController, Verify, VerifyRequestMethod, VerifyResponseController,
+    VerifyResponseStatus,
 };
 #[cfg(feature = "compress")]
 use super::CompressedFormat;
@@ -40,12 +42,11 @@ lazy_static! {
                 AllowedImageFormat::Webp,
             ],
             #[cfg(feature = "compress")]
-            CompressedFormat::WEBP,
+            CompressedFormat::Webp,
             super::api::UploadContentType::Multipart {
                 file_part_name: "smfile".to_owned(),
                 file_kind: FileKind::Stream,
             },
-            None,
             controller,
             5,
         );
@@ -68,12 +69,33 @@ lazy_static! {
             },
         );
 
+        let verify = Verify::new(
+            "/profile",
+            VerifyRequestMethod::Post { body: Map::new() },
+            VerifyResponseController::new(
+                VerifyResponseStatus::new("success", true),
+                "message".to_owned(),
+                Some(HashMap::from([
+                    ("username".to_owned(), "data.username".to_owned()),
+                    ("disk_usage".to_owned(), "data.disk_usage".to_owned()),
+                    ("disk_limit".to_owned(), "data.disk_limit".to_owned()),
+                ])),
+            ),
+        );
+
         let auth_method = AuthMethod::Header {
             key: None,
             prefix: None,
         };
 
-        Api::new("https://smms.app/api/v2", auth_method, upload, list, delete)
+        Api::new(
+            "https://smms.app/api/v2",
+            auth_method,
+            upload,
+            list,
+            delete,
+            verify,
+        )
     };
 }
 
@@ -83,19 +105,25 @@ pub struct SmMs {
 }
 
 impl SmMs {
-    pub fn new(token: String) -> Self {
-        let manager = BaseManager::new(
+    pub fn new(
+        token: String,
+        name_template: Option<&str>,
+        proxy: Option<&ProxyKind>,
+    ) -> Up2bResult<Self> {
+        let mut manager = BaseManager::new(
             ManagerCode::Smms.name(),
             SMMS_API.base_url().to_owned(),
             5,
             SMMS_API.allowed_formats(),
             Some(30),
             #[cfg(feature = "compress")]
-            CompressedFormat::WEBP,
-        );
+            CompressedFormat::Webp,
+            proxy,
+        )?;
+        manager.name_template = name_template.map(|t| t.to_owned());
 
         let inner = BaseApiManager::new(manager, token.clone(), &SMMS_API);
-        SmMs { inner }
+        Ok(SmMs { inner })
     }
 }
 
@@ -105,13 +133,17 @@ impl Manage for SmMs {
         self.inner.allowed_formats().to_vec()
     }
 
+    #[cfg(feature = "compress")]
+    fn set_compressed_format(&mut self, format: CompressedFormat) {
+        self.inner.set_compressed_format(format)
+    }
+
     fn support_stream(&self) -> bool {
         true
     }
 
     async fn verify(&self) -> Up2bResult<Option<Extra>> {
-        // TODO: api 类型的图床的 token 验证以后再实现
-        Ok(None)
+        self.inner.verify().await
     }
 
     async fn get_all_images(&self) -> Up2bResult<Vec<ImageItem>> {
@@ -0,0 +1,362 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use async_recursion::async_recursion;
+use futures::{stream, StreamExt};
+use regex::{Captures, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, WebviewWindow};
+use tokio::fs;
+
+use crate::{
+    config::APP_CONFIG_DIR,
+    get_manager,
+    manager::{ImageItem, Manage, ManagerCode, UploadResult},
+    upload::{download_image, upload_to},
+    upload_concurrency,
+    util::{image::remove_temp_image, time::now},
+    Up2bError, Up2bResult,
+};
+
+const MIGRATE_EVENT_NAME: &str = "migrate://progress";
+
+/// 链接正则编译后的大小限制，单位为字节
+const LINKS_REGEX_SIZE_LIMIT: usize = 256 * 1024 * 1024;
+
+/// 需要替换图片链接的文档扩展名
+const DOCUMENT_EXTENSIONS: [&str; 3] = ["md", "markdown", "html"];
+
+#[derive(Debug, Deserialize)]
+pub struct MigrateOptions {
+    pub source: ManagerCode,
+    pub target: ManagerCode,
+    /// 链接映射文件的保存路径，默认保存在配置目录的 migrations 目录中
+    pub mapping_file: Option<PathBuf>,
+    /// 迁移完成后替换此目录中所有文档里的旧链接
+    pub rewrite_dir: Option<PathBuf>,
+    /// 为 None 时使用配置中的并发数
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrateFailure {
+    url: String,
+    detail: Up2bError,
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrateReport {
+    total: usize,
+    /// 旧链接 -> 新链接
+    mapping: HashMap<String, String>,
+    failures: Vec<MigrateFailure>,
+    mapping_file: PathBuf,
+    /// 被修改的文档
+    rewritten: Vec<PathBuf>,
+}
+
+impl MigrateReport {
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn migrated(&self) -> usize {
+        self.mapping.len()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &str, &Up2bError)> {
+        self.failures
+            .iter()
+            .map(|f| (f.url.as_str(), f.code.as_str(), &f.detail))
+    }
+
+    pub fn mapping_file(&self) -> &Path {
+        &self.mapping_file
+    }
+
+    pub fn rewritten(&self) -> &[PathBuf] {
+        &self.rewritten
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct MigrateProgress<'a> {
+    done: usize,
+    total: usize,
+    url: &'a str,
+    success: bool,
+}
+
+/// 下载图片后上传到目标图床
+async fn migrate_image(
+    target: &dyn Manage,
+    target_code: &ManagerCode,
+    image: &ImageItem,
+) -> Up2bResult<String> {
+    let image_path = download_image(&image.url).await?;
+
+    // 迁移时不使用备用图床，必须上传到目标图床
+    let result = upload_to(target, target_code, None, 0, &image_path, false).await;
+
+    remove_temp_image(&image_path).await;
+
+    match result {
+        UploadResult::Response(r) => Ok(r.url),
+        UploadResult::Error { detail, .. } => Err(detail),
+    }
+}
+
+/// 将源图床中的所有图片迁移到目标图床，单张图片迁移失败不会中断迁移
+pub async fn migrate(
+    options: MigrateOptions,
+    window: Option<WebviewWindow>,
+) -> Up2bResult<MigrateReport> {
+    if options.source == options.target {
+        return Err(Up2bError::Other("源图床与目标图床相同".to_owned()));
+    }
+
+    let source = get_manager(&options.source).await?;
+    let target = get_manager(&options.target).await?;
+
+    let images = source.get_all_images().await?;
+    let total = images.len();
+
+    info!(
+        "迁移 {} 张图片：{:?} -> {:?}",
+        total, options.source, options.target
+    );
+
+    let concurrency = match options.concurrency {
+        Some(c) => c,
+        None => upload_concurrency().await,
+    };
+
+    let target = target.as_ref();
+    let target_code = &options.target;
+
+    // 闭包参数不能是引用，否则生成的 future 无法满足 tauri 命令的 Send 约束
+    let mut results = stream::iter(images)
+        .map(|image| async move {
+            let result = migrate_image(target, target_code, &image).await;
+            (image, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut mapping = HashMap::with_capacity(total);
+    let mut failures = Vec::new();
+    let mut done = 0;
+
+    while let Some((image, result)) = results.next().await {
+        done += 1;
+
+        if let Some(w) = &window {
+            let _ = w.emit(
+                MIGRATE_EVENT_NAME,
+                MigrateProgress {
+                    done,
+                    total,
+                    url: &image.url,
+                    success: result.is_ok(),
+                },
+            );
+        }
+
+        match result {
+            Ok(url) => {
+                debug!("图片已迁移：{} -> {}", image.url, url);
+                mapping.insert(image.url.clone(), url);
+            }
+            Err(e) => {
+                error!("迁移图片失败：{}, {}", image.url, e);
+                failures.push(MigrateFailure {
+                    url: image.url.clone(),
+                    code: e.as_string(),
+                    detail: e,
+                });
+            }
+        }
+    }
+
+    let mapping_file = match options.mapping_file {
+        Some(f) => f,
+        None => default_mapping_file(&options.source, &options.target).await?,
+    };
+
+    fs::write(&mapping_file, serde_json::to_string_pretty(&mapping)?).await?;
+
+    info!("链接映射已保存：{:?}", mapping_file);
+
+    let rewritten = match &options.rewrite_dir {
+        None => Vec::new(),
+        Some(dir) => rewrite_links(dir, &mapping).await?,
+    };
+
+    Ok(MigrateReport {
+        total,
+        mapping,
+        failures,
+        mapping_file,
+        rewritten,
+    })
+}
+
+async fn default_mapping_file(source: &ManagerCode, target: &ManagerCode) -> Up2bResult<PathBuf> {
+    let dir = APP_CONFIG_DIR.join("migrations");
+    fs::create_dir_all(&dir).await?;
+
+    Ok(dir.join(format!(
+        "{}-{}-{}.json",
+        source.name(),
+        target.name(),
+        now()?.as_secs()
+    )))
+}
+
+/// 匹配所有旧链接的正则，较长的链接在前，某个链接是另一个链接的前缀时优先匹配较长的链接
+fn links_regex(mapping: &HashMap<String, String>) -> Up2bResult<Option<Regex>> {
+    if mapping.is_empty() {
+        return Ok(None);
+    }
+
+    let mut links: Vec<&String> = mapping.keys().collect();
+    links.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    let pattern = links
+        .iter()
+        .map(|l| regex::escape(l))
+        .collect::<Vec<String>>()
+        .join("|");
+
+    // 迁移的链接可能有上万条，放宽编译后的大小限制
+    RegexBuilder::new(&pattern)
+        .size_limit(LINKS_REGEX_SIZE_LIMIT)
+        .build()
+        .map(Some)
+        .map_err(|e| Up2bError::Other(format!("无法生成链接的匹配规则：{}", e)))
+}
+
+/// 一次扫描替换文档中的全部旧链接，替换后的新链接不会再被替换
+fn replace_links<'a>(
+    content: &'a str,
+    re: &Regex,
+    mapping: &HashMap<String, String>,
+) -> Cow<'a, str> {
+    re.replace_all(content, |caps: &Captures| mapping[&caps[0]].clone())
+}
+
+/// 替换目录（包括子目录）中所有文档里的旧链接，返回被修改的文档
+pub async fn rewrite_links(
+    dir: &Path,
+    mapping: &HashMap<String, String>,
+) -> Up2bResult<Vec<PathBuf>> {
+    let re = match links_regex(mapping)? {
+        Some(re) => re,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut documents = Vec::new();
+    collect_documents(dir, &mut documents).await?;
+
+    let mut rewritten = Vec::new();
+
+    for path in documents {
+        let content = fs::read_to_string(&path).await?;
+
+        if let Cow::Owned(new_content) = replace_links(&content, &re, mapping) {
+            fs::write(&path, new_content).await?;
+            info!("已替换文档中的图片链接：{:?}", path);
+            rewritten.push(path);
+        }
+    }
+
+    Ok(rewritten)
+}
+
+#[async_recursion]
+async fn collect_documents(dir: &Path, documents: &mut Vec<PathBuf>) -> Up2bResult<()> {
+    let mut entries = fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if entry.file_type().await?.is_dir() {
+            collect_documents(&path, documents).await?;
+            continue;
+        }
+
+        let is_document = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| DOCUMENT_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+
+        if is_document {
+            documents.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(content: &str, pairs: &[(&str, &str)]) -> String {
+        let mapping: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let re = links_regex(&mapping).unwrap().unwrap();
+
+        replace_links(content, &re, &mapping).into_owned()
+    }
+
+    #[test]
+    fn prefers_longer_links() {
+        assert_eq!(
+            replace(
+                "![a](https://a.com/x.png) ![b](https://a.com/x.png.webp)",
+                &[
+                    ("https://a.com/x.png", "https://b.com/1.png"),
+                    ("https://a.com/x.png.webp", "https://b.com/2.webp"),
+                ],
+            ),
+            "![a](https://b.com/1.png) ![b](https://b.com/2.webp)"
+        );
+    }
+
+    #[test]
+    fn new_links_are_not_replaced_again() {
+        // 依次替换时 a 会先变成 b 再变成 c
+        assert_eq!(
+            replace(
+                "![](https://a.com/a.png) ![](https://a.com/b.png)",
+                &[
+                    ("https://a.com/a.png", "https://a.com/b.png"),
+                    ("https://a.com/b.png", "https://a.com/c.png"),
+                ],
+            ),
+            "![](https://a.com/b.png) ![](https://a.com/c.png)"
+        );
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(
+            replace(
+                "![](https://a.com/x.png?w=1) ![](https://a.com/xxpng?w=1)",
+                &[("https://a.com/x.png?w=1", "https://b.com/x.png")],
+            ),
+            "![](https://b.com/x.png) ![](https://a.com/xxpng?w=1)"
+        );
+    }
+
+    #[test]
+    fn empty_mapping_has_no_regex() {
+        assert!(links_regex(&HashMap::new()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{
+        header::{CONTENT_TYPE, ORIGIN},
+        HeaderMap, StatusCode,
+    },
+    routing::post,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{write_config, CONFIG},
+    manager::UploadResult,
+    upload::upload_sources,
+    upload_concurrency, using_uploader, Up2bError, Up2bResult,
+};
+
+/// 与 PicGo 的默认端口相同，使用 PicGo 服务的插件无需修改配置
+pub const DEFAULT_PORT: u16 = 36677;
+
+lazy_static! {
+    /// 应用内正在运行的服务
+    static ref SERVING: Mutex<Option<CancellationToken>> = Mutex::new(None);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// 请求需要带上 `?key=<token>`，为空时启动服务前生成随机 token 并保存到配置文件
+    #[serde(default)]
+    pub token: String,
+    /// 启动应用时是否自动启动服务
+    #[serde(default)]
+    pub auto_start: bool,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            token: String::new(),
+            auto_start: false,
+        }
+    }
+}
+
+/// PicGo 的上传请求体
+#[derive(Debug, Deserialize)]
+struct UploadRequest {
+    #[serde(default)]
+    list: Vec<String>,
+}
+
+/// 与 PicGo 的响应格式兼容，result 为上传成功的链接，fullResult 为每张图片的上传结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    result: Vec<String>,
+    full_result: Vec<UploadResult>,
+}
+
+impl UploadResponse {
+    fn error<S: Into<String>>(message: S) -> Self {
+        Self {
+            success: false,
+            message: Some(message.into()),
+            result: Vec::new(),
+            full_result: Vec::new(),
+        }
+    }
+}
+
+fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// 读取配置中的服务配置，没有 token 时生成随机 token 并保存到配置文件
+pub async fn load_config() -> Up2bResult<ServerConfig> {
+    let mut guard = CONFIG.write().await;
+
+    let mut server = guard.as_ref().map(|c| c.server()).unwrap_or_default();
+
+    if server.token.is_empty() {
+        server.token = generate_token();
+
+        if let Some(c) = guard.as_mut() {
+            c.set_server(server.clone());
+            write_config(c)?;
+        }
+
+        info!("已生成上传服务的 token");
+    }
+
+    Ok(server)
+}
+
+/// 浏览器中的网页也能向本地端口发送请求，只接受不带 Origin 的 json 请求和正确的 key
+fn check_request(
+    token: &str,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<(), (StatusCode, &'static str)> {
+    if headers.contains_key(ORIGIN) {
+        warn!("拒绝来自浏览器的上传请求：{:?}", headers.get(ORIGIN));
+        return Err((StatusCode::FORBIDDEN, "不接受来自浏览器的请求"));
+    }
+
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("application/json"));
+    if !is_json {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type 应为 application/json",
+        ));
+    }
+
+    if query.get("key").map(String::as_str) != Some(token) {
+        warn!("上传请求的 key 无效");
+        return Err((StatusCode::FORBIDDEN, "key 无效"));
+    }
+
+    Ok(())
+}
+
+async fn upload(
+    State(token): State<Arc<String>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<UploadResponse>) {
+    if let Err((status, message)) = check_request(&token, &headers, &query) {
+        return (status, Json(UploadResponse::error(message)));
+    }
+
+    // PicGo 在请求体为空时上传剪贴板中的图片，up2b 不支持
+    let request: UploadRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(UploadResponse::error(
+                    "请求体应为 {\"list\": [图片路径或链接]}",
+                )),
+            )
+        }
+    };
+
+    if request.list.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(UploadResponse::error("没有需要上传的图片")),
+        );
+    }
+
+    debug!("收到上传请求：{:?}", request.list);
+
+    let (manager, uploader) = match using_uploader().await {
+        Ok(u) => u,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(UploadResponse::error(e.to_string())),
+            )
+        }
+    };
+
+    let results = upload_sources(
+        uploader.as_ref(),
+        &manager,
+        None,
+        &request.list,
+        upload_concurrency().await,
+        false,
+    )
+    .await;
+
+    let urls: Vec<String> = results
+        .iter()
+        .filter_map(|r| match r {
+            UploadResult::Response(image) => Some(image.url.clone()),
+            UploadResult::Error { .. } => None,
+        })
+        .collect();
+
+    let success = urls.len() == results.len();
+
+    (
+        StatusCode::OK,
+        Json(UploadResponse {
+            success,
+            message: match success {
+                true => None,
+                false => Some("部分图片上传失败".to_owned()),
+            },
+            result: urls,
+            full_result: results,
+        }),
+    )
+}
+
+fn router(token: String) -> Router {
+    Router::new()
+        .route("/upload", post(upload))
+        .with_state(Arc::new(token))
+}
+
+/// 在 127.0.0.1 上监听配置的端口
+async fn bind(config: &ServerConfig) -> Up2bResult<TcpListener> {
+    if config.token.is_empty() {
+        return Err(Up2bError::Other("上传服务的 token 不能为空".to_owned()));
+    }
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+
+    let listener = TcpListener::bind(addr).await?;
+
+    info!("上传服务已启动：http://{}/upload", addr);
+
+    Ok(listener)
+}
+
+async fn run(listener: TcpListener, token: String, cancel: CancellationToken) -> Up2bResult<()> {
+    axum::serve(listener, router(token))
+        .with_graceful_shutdown(async move { cancel.cancelled().await })
+        .await?;
+
+    info!("上传服务已停止");
+
+    Ok(())
+}
+
+/// 在 127.0.0.1 上启动上传服务，直到 token 被取消
+pub async fn serve(config: ServerConfig, cancel: CancellationToken) -> Up2bResult<()> {
+    let listener = bind(&config).await?;
+
+    run(listener, config.token, cancel).await
+}
+
+/// 监听端口后在后台运行服务，已启动时先停止之前的服务
+pub async fn start(config: ServerConfig) -> Up2bResult<()> {
+    stop();
+
+    let listener = bind(&config).await?;
+
+    let cancel = CancellationToken::new();
+    *SERVING.lock().unwrap() = Some(cancel.clone());
+
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = run(listener, config.token, cancel.clone()).await {
+            error!("上传服务异常退出：{}", e);
+            cancel.cancel();
+        }
+    });
+
+    Ok(())
+}
+
+pub fn stop() {
+    if let Some(cancel) = SERVING.lock().unwrap().take() {
+        cancel.cancel();
+    }
+}
+
+pub fn is_serving() -> bool {
+    SERVING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|c| !c.is_cancelled())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn spawn_router() -> String {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, router("secret".to_owned()))
+                .await
+                .unwrap()
+        });
+
+        format!("http://{}/upload", addr)
+    }
+
+    async fn post(
+        url: &str,
+        content_type: &str,
+        origin: Option<&str>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = reqwest::Client::new()
+            .post(url)
+            .header(CONTENT_TYPE, content_type)
+            .body(r#"{"list": []}"#);
+        if let Some(o) = origin {
+            request = request.header(ORIGIN, o);
+        }
+
+        let resp = request.send().await.unwrap();
+        let status = StatusCode::from_u16(resp.status().as_u16()).unwrap();
+
+        (status, resp.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_browser_requests() {
+        let url = spawn_router().await;
+
+        let (status, body) = post(
+            &format!("{}?key=secret", url),
+            "application/json",
+            Some("https://example.com"),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["success"], false);
+    }
+
+    #[tokio::test]
+    async fn rejects_non_json_content_type() {
+        let url = spawn_router().await;
+
+        let (status, _) = post(&format!("{}?key=secret", url), "text/plain", None).await;
+
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_wrong_key() {
+        let url = spawn_router().await;
+
+        let (status, _) = post(&url, "application/json", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = post(&format!("{}?key=wrong", url), "application/json", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn accepts_json_with_key() {
+        let url = spawn_router().await;
+
+        // 通过检查后才会校验请求体，空列表直接返回错误，不会上传
+        let (status, body) = post(
+            &format!("{}?key=secret", url),
+            "application/json; charset=utf-8",
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "没有需要上传的图片");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_start_without_token() {
+        let config = ServerConfig {
+            port: 0,
+            ..Default::default()
+        };
+
+        assert!(bind(&config).await.is_err());
+    }
+}
@@ -1,9 +1,23 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use tauri::{App, Result, WebviewUrl, WebviewWindowBuilder};
 use tauri_plugin_cli::{ArgData, CliExt, Matches, SubcommandMatches};
+use tokio_util::sync::CancellationToken;
 
-use crate::{manager::UploadResult, using_manager};
+use crate::{
+    config::CONFIG,
+    document::{self, RewriteOptions},
+    manager::{ImageItem, ManagerCode, UploadResult},
+    migrate::{self, MigrateOptions},
+    server,
+    upload::{is_url, upload_sources},
+    upload_concurrency, using_uploader,
+    util::template::{file_stem, find_link_template, link_templates, render, LinkContext},
+    watch,
+};
 
 fn new_window(app: &App) {
     let builder = WebviewWindowBuilder::new(
@@ -31,7 +45,67 @@ fn new_window(app: &App) {
     builder.build().unwrap();
 }
 
-fn upload(command: Box<SubcommandMatches>) {
+/// 命令行上传结果的输出格式
+enum OutputFormat {
+    /// 输出完整的 json
+    Json,
+    /// 链接模板
+    Template(String),
+}
+
+impl OutputFormat {
+    /// 除 json 外的格式都是链接模板名，md 是 markdown 的别名
+    fn from_str(s: &str, custom: &HashMap<String, String>) -> Option<Self> {
+        let name = match s.to_lowercase().as_str() {
+            "json" => return Some(OutputFormat::Json),
+            "md" => "markdown".to_owned(),
+            n => n.to_owned(),
+        };
+
+        find_link_template(custom, &name)
+            .or_else(|| find_link_template(custom, s))
+            .map(OutputFormat::Template)
+    }
+
+    fn format(&self, image: &ImageItem, source: &str) -> String {
+        match self {
+            OutputFormat::Json => serde_json::to_string(image).unwrap(),
+            OutputFormat::Template(t) => {
+                // 本地图片可以获取尺寸，链接只使用文件名
+                let path = Path::new(source);
+                let image_path = if is_url(source) { None } else { Some(path) };
+
+                let mut context = LinkContext::new(image, image_path, None);
+                if image_path.is_none() {
+                    // 图床可能会重命名图片，文件名和 alt 以原链接为准
+                    let filename = source_filename(source);
+                    context.alt = file_stem(filename).to_owned();
+                    context.filename = filename.to_owned();
+                }
+
+                render(t, &context)
+            }
+        }
+    }
+}
+
+/// 图片路径或链接中的文件名
+fn source_filename(source: &str) -> &str {
+    let source = source.split(['?', '#']).next().unwrap_or(source);
+
+    Path::new(source)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(source)
+}
+
+/// 上传图片并按照编辑器（如 Typora）约定的格式输出：
+///
+/// - 每张上传成功的图片在标准输出中输出一行，顺序与输入一致
+/// - 错误信息只输出到标准错误
+/// - 有任意一张图片上传失败时返回非 0 的退出码
+fn upload(command: &SubcommandMatches) -> i32 {
+    // 图片可以是本地路径，也可以是 http(s) 链接
     let images: Vec<String> = command
         .matches
         .args
@@ -44,23 +118,346 @@ fn upload(command: Box<SubcommandMatches>) {
         .map(|v| v.as_str().unwrap().to_owned())
         .collect();
 
+    // 未指定并发数或并发数无效时使用配置中的并发数
+    let concurrency = command
+        .matches
+        .args
+        .get("concurrency")
+        .and_then(|a| a.value.as_str())
+        .and_then(|s| s.parse::<usize>().ok());
+
+    // 即使相同内容的图片已上传过也重新上传
+    let force = command
+        .matches
+        .args
+        .get("force")
+        .and_then(|a| a.value.as_bool())
+        .unwrap_or_default();
+
+    let format = command
+        .matches
+        .args
+        .get("format")
+        .and_then(|a| a.value.as_str())
+        .unwrap_or("url")
+        .to_owned();
+
     tokio::task::block_in_place(|| {
         tauri::async_runtime::block_on(async move {
-            let uploader = using_manager().await.unwrap();
+            let custom_templates = match CONFIG.read().await.as_ref() {
+                None => HashMap::new(),
+                Some(c) => c.link_templates().clone(),
+            };
+
+            let format = match OutputFormat::from_str(&format, &custom_templates) {
+                Some(f) => f,
+                None => {
+                    let names: Vec<String> = link_templates(&custom_templates)
+                        .into_iter()
+                        .map(|t| t.name)
+                        .collect();
+                    eprintln!(
+                        "不支持的输出格式：{}，可选值：{}、json",
+                        format,
+                        names.join("、")
+                    );
+                    return 2;
+                }
+            };
+
+            let (manager, uploader) = match using_uploader().await {
+                Ok(u) => u,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+
+            let concurrency = match concurrency {
+                Some(c) => c,
+                None => upload_concurrency().await,
+            };
+
+            let results = upload_sources(
+                uploader.as_ref(),
+                &manager,
+                None,
+                &images,
+                concurrency,
+                force,
+            )
+            .await;
 
-            for image in images.iter() {
-                let result = uploader.upload_image(None, 0, Path::new(image)).await;
+            let mut exit_code = 0;
+
+            for (source, result) in images.iter().zip(results.iter()) {
                 match result {
-                    UploadResult::Response(r) => println!("{}", r.url),
-                    UploadResult::Error { detail, .. } => println!("{}", detail),
+                    UploadResult::Response(r) => {
+                        println!("{}", format.format(r, source))
+                    }
+                    UploadResult::Error { detail, code } => {
+                        eprintln!("{}: [{}] {}", source, code, detail);
+                        exit_code = 1;
+                    }
                 }
             }
+
+            exit_code
         })
-    });
+    })
+}
+
+/// 命令行参数中的图床代码，不区分大小写
+fn parse_manager_code(s: &str) -> Option<ManagerCode> {
+    serde_json::from_value(serde_json::Value::String(s.to_uppercase())).ok()
+}
+
+/// 迁移图床，有任意一张图片迁移失败时返回非 0 的退出码
+fn migrate_images(command: &SubcommandMatches) -> i32 {
+    let arg = |name: &str| {
+        command
+            .matches
+            .args
+            .get(name)
+            .and_then(|a| a.value.as_str())
+            .map(|s| s.to_owned())
+    };
+
+    let (source, target) = match (
+        arg("from").as_deref().and_then(parse_manager_code),
+        arg("to").as_deref().and_then(parse_manager_code),
+    ) {
+        (Some(s), Some(t)) => (s, t),
+        _ => {
+            eprintln!("无效的图床：from={:?}, to={:?}", arg("from"), arg("to"));
+            return 2;
+        }
+    };
+
+    let options = MigrateOptions {
+        source,
+        target,
+        mapping_file: arg("output").map(PathBuf::from),
+        rewrite_dir: arg("rewrite").map(PathBuf::from),
+        concurrency: arg("concurrency").and_then(|s| s.parse::<usize>().ok()),
+    };
+
+    tokio::task::block_in_place(|| {
+        tauri::async_runtime::block_on(async move {
+            let report = match migrate::migrate(options, None).await {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+
+            for (url, code, detail) in report.failures() {
+                eprintln!("{}: [{}] {}", url, code, detail);
+            }
+
+            println!(
+                "已迁移 {}/{} 张图片，链接映射：{}",
+                report.migrated(),
+                report.total(),
+                report.mapping_file().display()
+            );
+
+            for path in report.rewritten() {
+                println!("已替换链接：{}", path.display());
+            }
+
+            if report.migrated() == report.total() {
+                0
+            } else {
+                1
+            }
+        })
+    })
+}
+
+/// 替换文档中的本地图片，有任意一张图片上传失败时返回非 0 的退出码
+fn rewrite_documents(command: &SubcommandMatches) -> i32 {
+    let documents: Vec<PathBuf> = command
+        .matches
+        .args
+        .get("documents")
+        .unwrap()
+        .value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| PathBuf::from(v.as_str().unwrap()))
+        .collect();
+
+    let flag = |name: &str| {
+        command
+            .matches
+            .args
+            .get(name)
+            .and_then(|a| a.value.as_bool())
+            .unwrap_or_default()
+    };
+
+    let dry_run = flag("dry-run");
+    let backup = !flag("no-backup");
+    let force = flag("force");
+
+    tokio::task::block_in_place(|| {
+        tauri::async_runtime::block_on(async move {
+            let mut exit_code = 0;
+
+            for path in documents {
+                let options = RewriteOptions {
+                    path: path.clone(),
+                    dry_run,
+                    backup,
+                    force,
+                };
+
+                let report = match document::rewrite_document(options, None).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("{}: {}", path.display(), e);
+                        exit_code = 1;
+                        continue;
+                    }
+                };
+
+                for image in report.images() {
+                    if let Some((code, detail)) = image.error() {
+                        eprintln!(
+                            "{}: {}: [{}] {}",
+                            path.display(),
+                            image.source(),
+                            code,
+                            detail
+                        );
+                        exit_code = 1;
+                    }
+                }
+
+                print!("{}", report.diff());
+
+                if let Some(backup) = report.backup() {
+                    eprintln!("已备份原文档：{}", backup.display());
+                }
+            }
+
+            exit_code
+        })
+    })
+}
+
+/// 在命令行中监视目录并自动上传新图片，直到按下 Ctrl+C
+fn watch_dirs(app: &App, command: &SubcommandMatches) -> i32 {
+    // 未指定目录时使用配置中的目录
+    let dirs: Vec<PathBuf> = command
+        .matches
+        .args
+        .get("dirs")
+        .and_then(|a| a.value.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let app = app.handle().clone();
+
+    tokio::task::block_in_place(|| {
+        tauri::async_runtime::block_on(async move {
+            let dirs = match dirs.is_empty() {
+                false => dirs,
+                true => match CONFIG.read().await.as_ref() {
+                    None => Vec::new(),
+                    Some(c) => c.watch_dirs().to_vec(),
+                },
+            };
+
+            let token = CancellationToken::new();
+
+            let ctrl_c = token.clone();
+            tauri::async_runtime::spawn(async move {
+                let _ = tokio::signal::ctrl_c().await;
+                ctrl_c.cancel();
+            });
+
+            match watch::watch(app, dirs, token).await {
+                Ok(_) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        })
+    })
+}
+
+/// 在命令行中启动兼容 PicGo 的本地上传服务，直到按下 Ctrl+C
+fn serve(command: &SubcommandMatches) -> i32 {
+    let arg = |name: &str| {
+        command
+            .matches
+            .args
+            .get(name)
+            .and_then(|a| a.value.as_str())
+            .map(|s| s.to_owned())
+    };
+
+    let port = match arg("port").map(|p| p.parse::<u16>()) {
+        None => None,
+        Some(Ok(p)) => Some(p),
+        Some(Err(_)) => {
+            eprintln!("无效的端口：{:?}", arg("port"));
+            return 2;
+        }
+    };
+    let token = arg("token");
+
+    tokio::task::block_in_place(|| {
+        tauri::async_runtime::block_on(async move {
+            let mut config = match server::load_config().await {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+
+            if let Some(p) = port {
+                config.port = p;
+            }
+            if let Some(t) = token {
+                config.token = t;
+            }
+
+            println!("token: {}", config.token);
+
+            let cancel = CancellationToken::new();
+
+            let ctrl_c = cancel.clone();
+            tauri::async_runtime::spawn(async move {
+                let _ = tokio::signal::ctrl_c().await;
+                ctrl_c.cancel();
+            });
+
+            match server::serve(config, cancel).await {
+                Ok(_) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        })
+    })
 }
 
 enum RunningMode {
-    Cli,
+    /// 命令行模式，值为退出码
+    Cli(i32),
     Windows,
 }
 
@@ -72,19 +469,34 @@ fn parse_cli_args(args: HashMap<String, ArgData>) {
     println!("{s}");
 }
 
-fn parse_cli_matches(matches: Matches) -> RunningMode {
-    if matches.args.len() > 0 {
+fn parse_cli_matches(app: &App, matches: Matches) -> RunningMode {
+    if !matches.args.is_empty() {
         debug!("cli args: {:?}", matches.args);
         parse_cli_args(matches.args);
-        return RunningMode::Cli;
+        return RunningMode::Cli(0);
     }
 
     if let Some(subcommond) = matches.subcommand {
         debug!("cli command: {:?}", subcommond);
         // 处理 upload 命令
         if subcommond.name == "upload" {
-            upload(subcommond);
-            return RunningMode::Cli;
+            return RunningMode::Cli(upload(&subcommond));
+        }
+
+        if subcommond.name == "rewrite" {
+            return RunningMode::Cli(rewrite_documents(&subcommond));
+        }
+
+        if subcommond.name == "migrate" {
+            return RunningMode::Cli(migrate_images(&subcommond));
+        }
+
+        if subcommond.name == "server" {
+            return RunningMode::Cli(serve(&subcommond));
+        }
+
+        if subcommond.name == "watch" {
+            return RunningMode::Cli(watch_dirs(app, &subcommond));
         }
     }
 
@@ -101,9 +513,30 @@ pub(crate) fn setup(app: &App) -> Result<()> {
         })
         .unwrap();
 
-    match parse_cli_matches(matches) {
-        RunningMode::Cli => app.handle().exit(0), // 不退出的话会一直阻塞在主线程循环里
-        RunningMode::Windows => new_window(app),
+    match parse_cli_matches(app, matches) {
+        RunningMode::Cli(code) => app.handle().exit(code), // 不退出的话会一直阻塞在主线程循环里
+        RunningMode::Windows => {
+            new_window(app);
+
+            tauri::async_runtime::spawn(async {
+                let auto_start = CONFIG
+                    .read()
+                    .await
+                    .as_ref()
+                    .is_some_and(|c| c.server().auto_start);
+                if !auto_start {
+                    return;
+                }
+
+                let result = match server::load_config().await {
+                    Ok(config) => server::start(config).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("自动启动上传服务失败：{}", e);
+                }
+            });
+        }
     }
 
     Ok(())
@@ -0,0 +1,738 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::{future::join_all, stream, StreamExt};
+use reqwest::StatusCode;
+use serde::Serialize;
+use tauri::WebviewWindow;
+use tokio::fs;
+
+use crate::{
+    config::{APP_CONFIG_DIR, CONFIG},
+    error::{PathError, UploadError},
+    get_manager,
+    history::{self, HistoryItem},
+    http::client_builder,
+    manager::{AllowedImageFormat, ImageItem, Manage, ManagerCode, UploadResult},
+    util::{
+        image::{guess_format, metadata::strip_metadata, remove_temp_image, temp_image_path},
+        time::now,
+    },
+    Up2bError, Up2bResult,
+};
+
+#[cfg(feature = "compress")]
+use crate::util::image::{compress::optimize, thumbnail::thumbnail, watermark::watermark};
+
+/// 下载远程图片的超时时间
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// 解码限制为 0（不限制）时下载图片的最大体积，单位为 MB
+const MAX_DOWNLOAD_SIZE: u64 = 512;
+
+lazy_static! {
+    /// 无法上传缩略图时，缩略图保存在此目录中
+    static ref THUMBNAIL_DIR: PathBuf = APP_CONFIG_DIR.join("thumbnails");
+}
+
+/// 上传单张图片到指定图床，上传成功后记录到上传历史中，记录失败不影响上传结果。
+///
+/// force 为 false 时，如果相同内容的图片已经上传到同一图床，直接返回已有的链接而不再上传。
+pub async fn upload_to(
+    uploader: &dyn Manage,
+    manager: &ManagerCode,
+    window: Option<WebviewWindow>,
+    id: u32,
+    image_path: &Path,
+    force: bool,
+) -> UploadResult {
+    let hash = match history::file_hash(image_path).await {
+        Ok(h) => Some(h),
+        Err(e) => {
+            warn!("计算图片哈希失败：{:?}, {}", image_path, e);
+            None
+        }
+    };
+
+    // 处理配置改变后，相同的图片也需要重新上传
+    let pipeline_hash = CONFIG
+        .read()
+        .await
+        .as_ref()
+        .map(|c| c.pipeline_hash(manager))
+        .unwrap_or_default();
+
+    if let (false, Some((hash, _))) = (force, &hash) {
+        match history::find_by_hash(manager, hash, &pipeline_hash).await {
+            Ok(Some(mut image)) => {
+                info!(
+                    "图片已上传过，使用已有链接：{:?} -> {}",
+                    image_path, image.url
+                );
+                image.set_manager(manager.clone());
+                return UploadResult::Response(image);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("查询上传历史失败：{}", e),
+        }
+    }
+
+    let prepared = match prepare_image(uploader, manager, window.as_ref(), image_path).await {
+        Ok(p) => p,
+        Err(e) => {
+            error!("处理图片失败：{:?}, {}", image_path, e);
+            return UploadResult::Error {
+                code: e.as_string(),
+                detail: e,
+            };
+        }
+    };
+
+    let source = prepared.as_deref().unwrap_or(image_path);
+
+    let mut result = uploader.upload_image(window, id, source).await;
+
+    // 上传到图床的缩略图的删除 id，删除原图时一并删除
+    #[cfg(feature = "compress")]
+    let thumb_deleted_id = match &mut result {
+        UploadResult::Response(image) => match attach_thumbnail(uploader, image, source).await {
+            Ok(id) => id,
+            Err(e) => {
+                warn!("生成缩略图失败：{:?}, {}", image_path, e);
+                None
+            }
+        },
+        UploadResult::Error { .. } => None,
+    };
+    #[cfg(not(feature = "compress"))]
+    let thumb_deleted_id = None;
+
+    if let Some(p) = &prepared {
+        remove_temp_image(p).await;
+    }
+
+    if let UploadResult::Response(image) = &mut result {
+        image.set_manager(manager.clone());
+
+        if let Some(hash) = hash {
+            if let Err(e) = history::record(
+                manager,
+                image,
+                thumb_deleted_id,
+                image_path,
+                hash,
+                pipeline_hash,
+            )
+            .await
+            {
+                warn!("记录上传历史失败：{:?}, {}", image_path, e);
+            }
+        }
+    }
+
+    result
+}
+
+/// 上传前处理图片，处理后的图片保存在临时文件中，不需要处理时返回 None。
+/// 上传历史中的哈希仍使用原图片计算
+async fn prepare_image(
+    uploader: &dyn Manage,
+    manager: &ManagerCode,
+    window: Option<&WebviewWindow>,
+    image_path: &Path,
+) -> Up2bResult<Option<PathBuf>> {
+    #[cfg(feature = "compress")]
+    {
+        // 先添加水印，之后的优化和体积检查都针对添加水印后的图片
+        let watermarked = watermark_image(image_path).await?;
+        let source = watermarked.as_deref().unwrap_or(image_path);
+
+        // 重新编码后的图片不包含元数据，不需要再去除
+        match optimize_image(uploader, window, source).await {
+            Ok(None) if watermarked.is_some() => return Ok(watermarked),
+            Ok(None) => {}
+            result => {
+                if let Some(p) = &watermarked {
+                    remove_temp_image(p).await;
+                }
+                return result;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "compress"))]
+    let _ = (uploader, window);
+
+    strip_image_metadata(manager, image_path).await
+}
+
+/// 图床没有返回缩略图时，按配置生成缩略图并上传到同一图床，
+/// 上传失败或配置为不上传时保存到本地。
+///
+/// 返回上传到图床的缩略图的删除 id
+#[cfg(feature = "compress")]
+async fn attach_thumbnail(
+    uploader: &dyn Manage,
+    image: &mut ImageItem,
+    image_path: &Path,
+) -> Up2bResult<Option<String>> {
+    if image.thumb().is_some() {
+        return Ok(None);
+    }
+
+    let (config, limits) = match CONFIG.read().await.as_ref() {
+        Some(c) => match c.thumbnail() {
+            Some(t) => (t.clone(), c.decode_limits()),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let filename = match image_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    let thumb = match thumbnail(image_path, &config, &limits).await? {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+
+    let ext = config.format.extension();
+
+    if config.upload && uploader.allowed_formats().contains(&config.format) {
+        let stem = filename
+            .rsplit_once('.')
+            .map_or(filename.as_str(), |(stem, _)| stem);
+        let path = temp_image_path(&format!("{}_thumb.{}", stem, ext)).await?;
+        fs::write(&path, &thumb).await?;
+
+        // 缩略图不记录到上传历史中
+        let result = uploader.upload_image(None, 0, &path).await;
+
+        remove_temp_image(&path).await;
+
+        match result {
+            UploadResult::Response(t) => {
+                debug!("缩略图已上传：{} -> {}", image.url, t.url);
+                image.set_thumb(t.url.clone());
+                return Ok(Some(t.deleted_id().to_owned()));
+            }
+            UploadResult::Error { detail, .. } => {
+                warn!("上传缩略图失败，保存到本地：{}", detail)
+            }
+        }
+    }
+
+    fs::create_dir_all(THUMBNAIL_DIR.as_path()).await?;
+
+    let path = THUMBNAIL_DIR.join(format!("{}.{}", uuid::Uuid::new_v4(), ext));
+    fs::write(&path, &thumb).await?;
+
+    debug!("缩略图已保存到本地：{} -> {:?}", image.url, path);
+
+    image.set_thumb(path.to_string_lossy().to_string());
+
+    Ok(None)
+}
+
+/// 删除上传记录中的缩略图。uploader 不为 None 时同时从图床中删除已上传的缩略图，
+/// 删除失败只记录日志
+pub async fn remove_thumbnail(uploader: Option<&dyn Manage>, item: &HistoryItem) {
+    if let (Some(uploader), Some(id)) = (uploader, item.thumb_deleted_id()) {
+        match uploader.delete_image(id).await {
+            Ok(r) if r.success() => debug!("已删除缩略图：{}", id),
+            Ok(r) => warn!("删除缩略图失败：{}, {:?}", id, r),
+            Err(e) => warn!("删除缩略图失败：{}, {}", id, e),
+        }
+    }
+
+    if let Some(thumb) = item.thumb() {
+        remove_local_thumbnail(thumb).await;
+    }
+}
+
+/// 删除保存在本地的缩略图，不是本地缩略图时什么也不做
+async fn remove_local_thumbnail(thumb: &str) {
+    let path = Path::new(thumb);
+
+    if !path.starts_with(THUMBNAIL_DIR.as_path()) {
+        return;
+    }
+
+    if let Err(e) = fs::remove_file(path).await {
+        warn!("删除本地缩略图失败：{:?}, {}", path, e);
+    }
+}
+
+/// 配置了水印时，将添加水印后的图片保存到同名的临时文件中
+#[cfg(feature = "compress")]
+async fn watermark_image(image_path: &Path) -> Up2bResult<Option<PathBuf>> {
+    let (config, limits) = match CONFIG.read().await.as_ref() {
+        Some(c) => match c.watermark() {
+            Some(w) => (w.clone(), c.decode_limits()),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let filename = match image_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    let watermarked = match watermark(image_path, &config, &limits).await? {
+        Some(w) => w,
+        None => return Ok(None),
+    };
+
+    let path = temp_image_path(&filename).await?;
+    fs::write(&path, &watermarked).await?;
+
+    debug!("已添加水印：{:?}", image_path);
+
+    Ok(Some(path))
+}
+
+/// 配置了图片优化时，将优化后的图片保存到扩展名为目标格式的临时文件中
+#[cfg(feature = "compress")]
+async fn optimize_image(
+    uploader: &dyn Manage,
+    window: Option<&WebviewWindow>,
+    image_path: &Path,
+) -> Up2bResult<Option<PathBuf>> {
+    let (options, limits) = match CONFIG.read().await.as_ref() {
+        Some(c) => match c.optimize() {
+            Some(o) => (o.clone(), c.decode_limits()),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    if !uploader.allowed_formats().contains(&options.format) {
+        warn!("图床不支持优化后的格式，不进行优化：{:?}", options.format);
+        return Ok(None);
+    }
+
+    let filename = match image_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    let optimized = match optimize(window, &filename, image_path, &options, &limits).await? {
+        Some(o) => o,
+        None => return Ok(None),
+    };
+
+    let stem = filename
+        .rsplit_once('.')
+        .map_or(filename.as_str(), |(stem, _)| stem);
+    let path = temp_image_path(&format!("{}.{}", stem, options.format.extension())).await?;
+
+    fs::write(&path, &optimized).await?;
+
+    Ok(Some(path))
+}
+
+/// 图床开启了去除元数据时，将去除元数据后的图片保存到同名的临时文件中
+async fn strip_image_metadata(
+    manager: &ManagerCode,
+    image_path: &Path,
+) -> Up2bResult<Option<PathBuf>> {
+    let enabled = CONFIG
+        .read()
+        .await
+        .as_ref()
+        .is_some_and(|c| c.strip_metadata(manager));
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    let filename = match image_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    let path = temp_image_path(&filename).await?;
+
+    let (src, dst) = (image_path.to_owned(), path.clone());
+    let stripped = tokio::task::spawn_blocking(move || strip_metadata(&src, &dst))
+        .await
+        .map_err(|e| Up2bError::Other(e.to_string()))?;
+
+    match stripped {
+        Ok(true) => {
+            debug!("已去除图片元数据：{:?} -> {:?}", image_path, path);
+            Ok(Some(path))
+        }
+        result => {
+            remove_temp_image(&path).await;
+            result.map(|_| None)
+        }
+    }
+}
+
+/// 上传单张图片，遇到网络错误或服务端错误时按顺序使用配置中的备用图床重试，
+/// 全部失败时返回原图床的错误
+pub async fn upload_image(
+    uploader: &dyn Manage,
+    manager: &ManagerCode,
+    window: Option<WebviewWindow>,
+    id: u32,
+    image_path: &Path,
+    force: bool,
+) -> UploadResult {
+    let result = upload_to(uploader, manager, window.clone(), id, image_path, force).await;
+
+    match &result {
+        UploadResult::Error { detail, .. } if detail.is_retryable() => {}
+        _ => return result,
+    }
+
+    let fallbacks = match CONFIG.read().await.as_ref() {
+        None => return result,
+        Some(c) => c.fallbacks().to_vec(),
+    };
+
+    for fallback in fallbacks.iter().filter(|f| *f != manager) {
+        warn!(
+            "上传失败，使用备用图床重试：{:?} -> {:?}",
+            manager, fallback
+        );
+
+        let uploader = match get_manager(fallback).await {
+            Ok(u) => u,
+            Err(e) => {
+                error!("备用图床不可用：{:?}, {}", fallback, e);
+                continue;
+            }
+        };
+
+        match upload_to(
+            uploader.as_ref(),
+            fallback,
+            window.clone(),
+            id,
+            image_path,
+            force,
+        )
+        .await
+        {
+            UploadResult::Response(image) => return UploadResult::Response(image),
+            UploadResult::Error { detail, .. } => {
+                error!("备用图床上传失败：{:?}, {}", fallback, detail)
+            }
+        }
+    }
+
+    result
+}
+
+#[derive(Debug, Serialize)]
+pub struct MirrorError {
+    manager: ManagerCode,
+    detail: Up2bError,
+    code: String,
+}
+
+/// 镜像上传的结果
+#[derive(Debug, Serialize)]
+pub struct MirrorUploadResult {
+    /// 按镜像图床的顺序第一个上传成功的图片，全部失败时为 None
+    primary: Option<ImageItem>,
+    /// 其他上传成功的图片，可作为备用链接
+    fallbacks: Vec<ImageItem>,
+    errors: Vec<MirrorError>,
+}
+
+/// 同时上传一张图片到多个图床，单个图床上传失败不影响其他图床。
+///
+/// 只有第一个图床会发送上传进度事件。
+pub async fn upload_image_mirror(
+    managers: &[ManagerCode],
+    window: Option<WebviewWindow>,
+    id: u32,
+    image_path: &Path,
+    force: bool,
+) -> MirrorUploadResult {
+    info!("镜像上传图片 {:?} 到：{:?}", image_path, managers);
+
+    let tasks = managers.iter().enumerate().map(|(index, manager)| {
+        let window = if index == 0 { window.clone() } else { None };
+        async move {
+            let result = match get_manager(manager).await {
+                Ok(uploader) => {
+                    upload_to(uploader.as_ref(), manager, window, id, image_path, force).await
+                }
+                Err(e) => UploadResult::Error {
+                    code: e.as_string(),
+                    detail: e,
+                },
+            };
+
+            (manager.clone(), result)
+        }
+    });
+
+    let mut result = MirrorUploadResult {
+        primary: None,
+        fallbacks: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for (manager, r) in join_all(tasks).await {
+        match r {
+            UploadResult::Response(image) => {
+                if result.primary.is_none() {
+                    result.primary = Some(image);
+                } else {
+                    result.fallbacks.push(image);
+                }
+            }
+            UploadResult::Error { detail, code } => {
+                error!("镜像上传失败：manager={:?}, {}", manager, detail);
+                result.errors.push(MirrorError {
+                    manager,
+                    detail,
+                    code,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// 并发上传多张图片，返回的结果与输入顺序一致，单张图片上传失败不会中断其他图片的上传。
+///
+/// 每张图片在输入中的索引作为其上传进度事件中的 id。
+pub async fn upload_images(
+    uploader: &dyn Manage,
+    manager: &ManagerCode,
+    window: Option<WebviewWindow>,
+    image_paths: &[PathBuf],
+    concurrency: usize,
+    force: bool,
+) -> Vec<UploadResult> {
+    let concurrency = concurrency.max(1);
+
+    info!(
+        "批量上传 {} 张图片，并发数：{}",
+        image_paths.len(),
+        concurrency
+    );
+
+    // 闭包参数不能是引用，否则生成的 future 无法满足 tauri 命令的 Send 约束
+    stream::iter(image_paths.iter().cloned().enumerate())
+        .map(|(index, image_path)| {
+            let window = window.clone();
+            async move {
+                trace!("上传图片 {index}: {image_path:?}");
+                upload_image(uploader, manager, window, index as u32, &image_path, force).await
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+/// 上传本地路径或 http(s) 链接，链接指向的图片会先下载到临时目录
+pub async fn upload_sources(
+    uploader: &dyn Manage,
+    manager: &ManagerCode,
+    window: Option<WebviewWindow>,
+    sources: &[String],
+    concurrency: usize,
+    force: bool,
+) -> Vec<UploadResult> {
+    let mut prepared = Vec::with_capacity(sources.len());
+
+    for source in sources.iter() {
+        if is_url(source) {
+            prepared.push(download_image(source).await);
+        } else {
+            prepared.push(Ok(PathBuf::from(source)));
+        }
+    }
+
+    let image_paths: Vec<PathBuf> = prepared
+        .iter()
+        .filter_map(|r| r.as_ref().ok().cloned())
+        .collect();
+
+    let mut uploaded = upload_images(uploader, manager, window, &image_paths, concurrency, force)
+        .await
+        .into_iter();
+
+    for path in image_paths.iter() {
+        remove_temp_image(path).await;
+    }
+
+    prepared
+        .into_iter()
+        .map(|r| match r {
+            Ok(_) => uploaded.next().unwrap(),
+            Err(e) => UploadResult::Error {
+                code: e.as_string(),
+                detail: e,
+            },
+        })
+        .collect()
+}
+
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// 去掉文件名中的路径部分，并使扩展名与图片实际格式一致
+fn normalize_filename(filename: Option<&str>, prefix: &str, format: &AllowedImageFormat) -> String {
+    let name = filename
+        .and_then(|f| Path::new(f).file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) => (stem.to_owned(), Some(ext)),
+        None => (name.clone(), None),
+    };
+
+    let stem = if stem.is_empty() {
+        format!(
+            "{}_{}",
+            prefix,
+            now().map(|d| d.as_millis()).unwrap_or_default()
+        )
+    } else {
+        stem
+    };
+
+    match ext.and_then(AllowedImageFormat::from_extension) {
+        Some(f) if &f == format => name,
+        _ => format!("{}.{}", stem, format.extension()),
+    }
+}
+
+/// 将图片数据（比如剪贴板中的截图）保存为临时文件，之后与本地图片一样上传
+pub async fn save_image_data(data: &[u8], filename: Option<&str>) -> Up2bResult<PathBuf> {
+    let format = match guess_format(data) {
+        Some(f) => f,
+        None => return Err(UploadError::UnsupportedFormat.into()),
+    };
+
+    let filename = normalize_filename(filename, "clipboard", &format);
+    let path = temp_image_path(&filename).await?;
+
+    fs::write(&path, data).await?;
+
+    debug!("图片数据已保存到临时文件：{:?}", path);
+
+    Ok(path)
+}
+
+/// 读取响应体，超过 max_size MB 时立即停止。超过解码限制的图片也无法处理，不必下载完整内容
+async fn read_limited(mut resp: reqwest::Response, max_size: u64) -> Up2bResult<Vec<u8>> {
+    let limit = max_size * 1024 * 1024;
+    if resp.content_length().is_some_and(|l| l > limit) {
+        return Err(UploadError::TooLarge(max_size).into());
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if data.len() as u64 + chunk.len() as u64 > limit {
+            return Err(UploadError::TooLarge(max_size).into());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+/// 下载远程图片到临时目录，请求会使用配置中的代理
+pub async fn download_image(url: &str) -> Up2bResult<PathBuf> {
+    if !is_url(url) {
+        return Err(Up2bError::Other(format!("不是 http(s) 链接：{}", url)));
+    }
+
+    let (proxy, max_size) = match CONFIG.read().await.as_ref() {
+        None => (None, MAX_DOWNLOAD_SIZE),
+        Some(c) => (
+            c.active_proxy().cloned(),
+            match c.decode_limits().max_alloc {
+                0 => MAX_DOWNLOAD_SIZE,
+                m => m,
+            },
+        ),
+    };
+
+    let client = client_builder(proxy.as_ref())?
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?;
+
+    debug!("下载图片：{}", url);
+
+    let resp = client.get(url).send().await?;
+
+    let status = resp.status();
+    if status != StatusCode::OK {
+        error!("下载图片失败：url={}, status={}", url, status);
+        return Err(Up2bError::Status(status));
+    }
+
+    let data = read_limited(resp, max_size).await?;
+
+    let format = match guess_format(&data) {
+        Some(f) => f,
+        None => return Err(UploadError::UnsupportedFormat.into()),
+    };
+
+    // 链接中的最后一段作为文件名
+    let filename = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty());
+
+    let filename = normalize_filename(filename, "remote", &format);
+    let path = temp_image_path(&filename).await?;
+
+    fs::write(&path, &data).await?;
+
+    info!("图片已下载：url={}, path={:?}", url, path);
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes, Router};
+
+    use super::*;
+    use crate::http::mock;
+
+    async fn get(size: usize) -> reqwest::Response {
+        let body = Bytes::from(vec![0u8; size]);
+        let url = mock::serve(Router::new().fallback(move |_: Bytes| async move { body })).await;
+
+        reqwest::get(url).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_limited_rejects_large_body() {
+        let e = read_limited(get(1024 * 1024 + 1).await, 1)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(e, Up2bError::Upload(UploadError::TooLarge(1))),
+            "{e:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn read_limited_reads_small_body() {
+        let data = read_limited(get(1024 * 1024).await, 1).await.unwrap();
+        assert_eq!(data.len(), 1024 * 1024);
+    }
+}
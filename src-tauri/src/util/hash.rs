@@ -0,0 +1,39 @@
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// 每次读取的字节数
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// 分块读取并计算 sha256，返回十六进制的哈希值和读取的字节数，不会将全部内容读入内存
+pub async fn sha256<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut size = 0;
+
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_in_chunks() {
+        let data: Vec<u8> = (0..BUFFER_SIZE * 2 + 7).map(|i| i as u8).collect();
+
+        let (hash, size) = sha256(&mut data.as_slice()).await.unwrap();
+
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(hash, hex::encode(Sha256::digest(&data)));
+    }
+}
@@ -1,39 +1,20 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use image::io::Reader;
-
-use crate::{util::time::now, Error};
-
-use {
-    crate::{manager::CompressedFormat, Result},
-    image::{
-        imageops::{self, FilterType},
-        DynamicImage,
-    },
-    serde::Serialize,
-    std::io::Cursor,
-    tauri::Window,
-    tokio::{fs::File, io::AsyncReadExt},
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageReader, Limits,
 };
+use serde::Serialize;
+use tauri::{Emitter, WebviewWindow};
+use tokio::fs::File;
 
-lazy_static! {
-    pub static ref TEMP_DIR: PathBuf = std::env::temp_dir().join("up2b-".to_owned() + &now().unwrap().as_secs().to_string()); // 不能重名，加时间后缀
-}
-
-fn compress_to_jpeg(img: DynamicImage, max_size: u64, file_size: u64) -> Result<DynamicImage> {
-    let quality: u8 = (max_size * 100 / file_size) as u8;
-    let mut compressed_img = DynamicImage::new_rgba8(img.width(), img.height());
-
-    // 将原始图片的像素拷贝到压缩图片中
-    imageops::replace(&mut compressed_img, &img.to_rgba8(), 0, 0);
-
-    // 获取压缩后的 JPEG 数据
-    let mut buffer = Vec::new();
-    let mut cursor = Cursor::new(&mut buffer);
-    compressed_img.write_to(&mut cursor, image::ImageOutputFormat::Jpeg(quality))?;
+use crate::{
+    manager::{AllowedImageFormat, CompressedFormat},
+    Up2bError, Up2bResult,
+};
 
-    Ok(image::load_from_memory(&buffer)?)
-}
+use super::{guess_still_format, png, temp_image_path, DecodeLimits, OptimizeConfig};
 
 const COMPRESS_EVENT_NAME: &str = "upload://compress";
 
@@ -46,27 +27,214 @@ enum CompressEvent {
         filename: String,
         original: u64,
         compressed: u64,
+        quality: u8,
+        width: u32,
+        height: u32,
     },
 }
 
+/// 直接从文件解码，不需要先将整个文件读入内存
+fn decode(path: &Path, limits: &DecodeLimits) -> Up2bResult<DynamicImage> {
+    let mut image_limits = Limits::default();
+    image_limits.max_image_width = limits.max_width;
+    image_limits.max_image_height = limits.max_height;
+    image_limits.max_alloc = match limits.max_alloc {
+        0 => None,
+        n => Some(n * 1024 * 1024),
+    };
+
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(image_limits);
+
+    // 重新编码后的图片不包含 EXIF，需要在解码时按 EXIF 中的方向旋转
+    let result = reader.into_decoder().and_then(|mut decoder| {
+        let orientation = decoder.orientation()?;
+        let mut img = DynamicImage::from_decoder(decoder)?;
+        img.apply_orientation(orientation);
+        Ok(img)
+    });
+
+    match result {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            error!("读取图片失败：{}", e);
+            Err(Up2bError::Image(e))
+        }
+    }
+}
+
+/// 编解码比较耗时，在阻塞线程中执行，不阻塞异步运行时
+pub(super) async fn blocking<T, F>(f: F) -> Up2bResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Up2bResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Up2bError::Other(e.to_string()))?
+}
+
+/// 二分查找时的质量范围，低于最低质量时改为缩小尺寸
+const MIN_QUALITY: u8 = 30;
+const MAX_QUALITY: u8 = 95;
+
+/// 最低质量仍超过限制时，最多缩小尺寸的次数
+const MAX_RESIZE_ROUNDS: usize = 8;
+
+/// 每次缩小尺寸时至少缩小到原来的 90%
+const MAX_RESIZE_RATIO: f64 = 0.9;
+
+/// 缩小后的最短边不能小于此值
+const MIN_EDGE: u32 = 64;
+
+/// 压缩的目标
+pub struct CompressTarget<'a> {
+    /// 图床名，无法压缩到限制以内时用于错误信息
+    pub image_bed_name: &'a str,
+    pub max_size: u64,
+    /// 安全余量，压缩后的体积不超过 max_size 的 (100 - margin)%
+    pub margin: u8,
+    pub format: &'a CompressedFormat,
+}
+
+impl CompressTarget<'_> {
+    fn target_size(&self) -> u64 {
+        self.max_size * u64::from(100 - self.margin.min(99)) / 100
+    }
+}
+
+struct Compressed {
+    data: Vec<u8>,
+    quality: u8,
+    width: u32,
+    height: u32,
+}
+
+/// 在质量范围内二分查找不超过目标体积的最高质量，最低质量也超过时返回 Err(最低质量时的体积)
+fn search_quality(
+    img: &DynamicImage,
+    format: &AllowedImageFormat,
+    target_size: u64,
+) -> Up2bResult<Result<(Vec<u8>, u8), u64>> {
+    let (mut low, mut high) = (MIN_QUALITY, MAX_QUALITY);
+    let mut best = None;
+    let mut smallest = u64::MAX;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let data = encode_with_quality(img, format, quality)?;
+        let size = data.len() as u64;
+
+        debug!(
+            "压缩尝试：{} x {}, quality={}, size={}",
+            img.width(),
+            img.height(),
+            quality,
+            size
+        );
+
+        if size <= target_size {
+            best = Some((data, quality));
+            low = quality + 1;
+        } else {
+            smallest = smallest.min(size);
+            high = quality - 1;
+        }
+    }
+
+    Ok(best.ok_or(smallest))
+}
+
+/// 先降低质量，最低质量仍超过目标体积时再缩小尺寸
+fn compress_to_fit(
+    image_path: &Path,
+    limits: &DecodeLimits,
+    target: &CompressTarget,
+) -> Up2bResult<Compressed> {
+    let format = target.format.image_format();
+    let target_size = target.target_size();
+
+    let mut img = decode(image_path, limits)?;
+    match format {
+        // jpeg 不支持透明通道，只转换一次
+        AllowedImageFormat::Jpeg => img = DynamicImage::ImageRgb8(img.to_rgb8()),
+        // 先尝试无损优化，截图等颜色较少的图片通常可以直接满足限制
+        AllowedImageFormat::Png => {
+            let data = png::optimize_lossless(&img)?;
+            debug!("png 无损优化后的体积：{}", data.len());
+
+            if data.len() as u64 <= target_size {
+                return Ok(Compressed {
+                    data,
+                    quality: 100,
+                    width: img.width(),
+                    height: img.height(),
+                });
+            }
+        }
+        _ => {}
+    }
+
+    let mut smallest = u64::MAX;
+
+    for _ in 0..=MAX_RESIZE_ROUNDS {
+        let size = match search_quality(&img, &format, target_size)? {
+            Ok((data, quality)) => {
+                return Ok(Compressed {
+                    data,
+                    quality,
+                    width: img.width(),
+                    height: img.height(),
+                })
+            }
+            Err(size) => size,
+        };
+
+        smallest = smallest.min(size);
+
+        // 体积与像素数近似成正比
+        let ratio = (target_size as f64 / size as f64)
+            .sqrt()
+            .min(MAX_RESIZE_RATIO);
+        let width = (img.width() as f64 * ratio) as u32;
+        let height = (img.height() as f64 * ratio) as u32;
+
+        if width.min(height) < MIN_EDGE {
+            break;
+        }
+
+        img = img.resize_exact(width, height, FilterType::Lanczos3);
+    }
+
+    error!(
+        "无法将图片压缩到限制以内：{:?}, {} > {}",
+        image_path, smallest, target_size
+    );
+
+    Err(Up2bError::OverSize(
+        target.image_bed_name.to_owned(),
+        image_path.to_string_lossy().to_string(),
+        target.max_size,
+        smallest,
+    ))
+}
+
+/// 返回需要上传的文件和它的路径，压缩后的图片保存在临时文件中，扩展名与压缩格式一致
 pub async fn compress(
-    window: Option<&Window>,
-    max_size: u64,
+    window: Option<&WebviewWindow>,
+    target: &CompressTarget<'_>,
     file_size: u64,
     filename: &str,
-    mut image_file: File,
-    compressed_format: &CompressedFormat,
-) -> Result<File> {
-    if max_size >= file_size {
-        match window {
-            Some(w) =>
-            // 通知前端不需要压缩
-            {
-                w.emit(COMPRESS_EVENT_NAME, &CompressEvent::No)?;
-            }
-            None => {}
+    image_path: &Path,
+    image_file: File,
+    limits: &DecodeLimits,
+) -> Up2bResult<(File, PathBuf)> {
+    if target.max_size >= file_size {
+        // 通知前端不需要压缩
+        if let Some(w) = window {
+            w.emit(COMPRESS_EVENT_NAME, &CompressEvent::No)?;
         }
-        return Ok(image_file);
+        return Ok((image_file, image_path.to_owned()));
     }
 
     if let Some(w) = window {
@@ -75,73 +243,286 @@ pub async fn compress(
 
     info!("图片尺寸超过图床限制，正在压缩图片。");
 
-    let mut buf = vec![];
-    image_file.read_to_end(&mut buf).await?;
-    trace!("已读取图片到缓存");
+    let filename_without_ext = filename.rsplitn(2, ".").collect::<Vec<&str>>()[1].to_owned();
 
-    #[cfg(not(feature = "no-limits"))]
-    let reader = Reader::new(Cursor::new(buf)).with_guessed_format()?;
+    let compressed = {
+        let image_path = image_path.to_owned();
+        let limits = limits.clone();
+        // 闭包需要 'static，借用的字段转为 owned 后在闭包中重新构造
+        let image_bed_name = target.image_bed_name.to_owned();
+        let format = target.format.clone();
+        let (max_size, margin) = (target.max_size, target.margin);
 
-    #[cfg(feature = "no-limits")]
-    let reader = {
-        let mut r = Reader::new(Cursor::new(buf)).with_guessed_format()?;
+        blocking(move || {
+            compress_to_fit(
+                &image_path,
+                &limits,
+                &CompressTarget {
+                    image_bed_name: &image_bed_name,
+                    max_size,
+                    margin,
+                    format: &format,
+                },
+            )
+        })
+        .await?
+    };
 
-        r.no_limits();
+    let path = temp_image_path(&format!(
+        "{}.{}",
+        filename_without_ext,
+        target.format.image_format().extension()
+    ))
+    .await?;
+    tokio::fs::write(&path, &compressed.data).await?;
 
-        r
-    };
+    let file = File::open(&path).await?;
 
-    let img = match reader.decode() {
-        Ok(p) => p,
-        Err(e) => {
-            error!("读取图片失败：{}", e);
-            return Err(Error::Image(e));
-        }
-    };
+    let size = compressed.data.len() as u64;
 
-    debug!("要保存的图片格式：{:?}", compressed_format);
+    debug!(
+        "压缩图片已保存到本地：{:?}，压缩后体积：{}，质量：{}，尺寸：{} x {}",
+        path, size, compressed.quality, compressed.width, compressed.height
+    );
 
-    let filename_without_ext = filename.rsplitn(2, ".").collect::<Vec<&str>>()[1];
+    if let Some(w) = window {
+        w.emit(
+            COMPRESS_EVENT_NAME,
+            &CompressEvent::End {
+                filename: filename.into(),
+                original: file_size,
+                compressed: size,
+                quality: compressed.quality,
+                width: compressed.width,
+                height: compressed.height,
+            },
+        )?;
+    }
+
+    Ok((file, path))
+}
 
-    let path = match compressed_format {
-        CompressedFormat::JPEG => {
-            let img = compress_to_jpeg(img, max_size, file_size)?;
-            let p = TEMP_DIR.join(filename_without_ext.to_owned() + ".jpeg");
-            img.save(&p)?;
-            p
+/// 保持宽高比，缩放到 max x max 以内
+pub(super) fn fit_dimension(img: DynamicImage, max_dimension: Option<u32>) -> DynamicImage {
+    match max_dimension {
+        Some(max) if img.width() > max || img.height() > max => {
+            img.resize(max, max, FilterType::Lanczos3)
         }
-        CompressedFormat::WEBP => {
-            let scale = (file_size as f64 / max_size as f64).sqrt().ceil();
-            // 设置目标尺寸
-            let target_width = (img.width() as f64 / scale).floor() as u32;
-            let target_height = (img.height() as f64 / scale).floor() as u32;
-            debug!("压缩后的图片尺寸：{} x {}", target_width, target_height);
-
-            // 缩放图像
-            let img = img.resize_exact(target_width, target_height, FilterType::Lanczos3);
-
-            let p = TEMP_DIR.join(filename_without_ext.to_owned() + ".webp");
-            img.save(&p)?;
-            p
+        _ => img,
+    }
+}
+
+/// 按指定质量编码
+pub(super) fn encode_with_quality(
+    img: &DynamicImage,
+    format: &AllowedImageFormat,
+    quality: u8,
+) -> Up2bResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+
+    match format {
+        AllowedImageFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut buffer, quality);
+            match img {
+                DynamicImage::ImageRgb8(_) | DynamicImage::ImageLuma8(_) => {
+                    img.write_with_encoder(encoder)?
+                }
+                // jpeg 不支持透明通道
+                _ => DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)?,
+            }
         }
-    };
+        AllowedImageFormat::Webp => {
+            // image 只支持无损 webp，有损编码使用 libwebp
+            let rgba = img.to_rgba8();
+            let data =
+                webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode(quality as f32);
+            buffer.extend_from_slice(&data);
+        }
+        AllowedImageFormat::Avif => {
+            let encoder = AvifEncoder::new_with_speed_quality(&mut buffer, 8, quality);
+            DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(encoder)?;
+        }
+        AllowedImageFormat::Png => buffer = png::quantize(img, quality)?,
+        _ => {
+            return Err(Up2bError::Other(format!(
+                "不支持编码为此格式：{:?}",
+                format
+            )))
+        }
+    }
 
-    let file = File::open(&path).await?;
+    Ok(buffer)
+}
 
-    let size = file.metadata().await?.len();
+/// 按配置缩小尺寸并重新编码
+pub(super) fn encode(img: DynamicImage, options: &OptimizeConfig) -> Up2bResult<Vec<u8>> {
+    let img = fit_dimension(img, options.max_dimension);
 
-    debug!("压缩图片已保存到本地：{:?}，压缩后体积：{}", path, size);
+    encode_with_quality(&img, &options.format, options.quality.clamp(1, 100))
+}
+
+/// 上传前优化图片：转换格式、缩小尺寸并按质量重新编码，结果比原图大时返回 None。
+///
+/// 动图解码后会丢失动画，image 也不支持解码 avif，动图和 avif 不进行优化
+pub async fn optimize(
+    window: Option<&WebviewWindow>,
+    filename: &str,
+    image_path: &Path,
+    options: &OptimizeConfig,
+    limits: &DecodeLimits,
+) -> Up2bResult<Option<Vec<u8>>> {
+    if matches!(
+        guess_still_format(image_path).await?,
+        Some(AllowedImageFormat::Avif) | None
+    ) {
+        return Ok(None);
+    }
+
+    if let Some(w) = window {
+        w.emit(COMPRESS_EVENT_NAME, &CompressEvent::Start)?;
+    }
+
+    let original = tokio::fs::metadata(image_path).await?.len();
+
+    let format = options.format.clone();
+    let quality = options.quality.clamp(1, 100);
+    let max_dimension = options.max_dimension;
+
+    let (optimized, width, height) = decode_with(image_path, limits, move |img| {
+        let img = fit_dimension(img, max_dimension);
+        Ok((
+            encode_with_quality(&img, &format, quality)?,
+            img.width(),
+            img.height(),
+        ))
+    })
+    .await?;
+
+    let compressed = optimized.len() as u64;
+
+    if compressed >= original {
+        debug!(
+            "优化后的图片更大，使用原图：{}, {} -> {}",
+            filename, original, compressed
+        );
+
+        if let Some(w) = window {
+            w.emit(COMPRESS_EVENT_NAME, &CompressEvent::No)?;
+        }
+
+        return Ok(None);
+    }
+
+    info!("图片已优化：{}, {} -> {}", filename, original, compressed);
 
     if let Some(w) = window {
         w.emit(
             COMPRESS_EVENT_NAME,
             &CompressEvent::End {
                 filename: filename.into(),
-                original: file_size,
-                compressed: size,
+                original,
+                compressed,
+                quality,
+                width,
+                height,
             },
         )?;
     }
 
-    Ok(file)
+    Ok(Some(optimized))
+}
+
+/// 在阻塞线程中解码图片并处理
+pub(super) async fn decode_with<T, F>(
+    image_path: &Path,
+    limits: &DecodeLimits,
+    f: F,
+) -> Up2bResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(DynamicImage) -> Up2bResult<T> + Send + 'static,
+{
+    let image_path = image_path.to_owned();
+    let limits = limits.clone();
+
+    blocking(move || f(decode(&image_path, &limits)?)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::http::mock;
+
+    /// 在 SOI 之后插入只包含方向的 EXIF
+    fn with_orientation(jpeg: &[u8], orientation: u8) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[orientation, 0, 0, 0, 0, 0, 0]);
+
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend(tiff);
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        data.extend(payload);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[tokio::test]
+    async fn decode_applies_orientation() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(4, 2));
+        let jpeg = encode_with_quality(&img, &AllowedImageFormat::Jpeg, 90).unwrap();
+
+        let path = mock::temp_file("a.jpg", &with_orientation(&jpeg, 6)).await;
+        let decoded = decode(&path, &DecodeLimits::default()).unwrap();
+
+        assert_eq!((decoded.width(), decoded.height()), (2, 4));
+    }
+
+    /// 有噪点的图片，质量越低体积越小
+    fn noisy_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            let v = (x * 31 + y * 17 + x * y * 7) as u8;
+            image::Rgb([v, v.wrapping_mul(3), v.wrapping_add(97)])
+        }))
+    }
+
+    #[test]
+    fn search_quality_uses_highest_quality_when_it_fits() {
+        let (_, quality) = search_quality(&noisy_image(), &AllowedImageFormat::Jpeg, u64::MAX)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(quality, MAX_QUALITY);
+    }
+
+    #[test]
+    fn search_quality_returns_smallest_size_when_nothing_fits() {
+        let img = noisy_image();
+        let smallest = encode_with_quality(&img, &AllowedImageFormat::Jpeg, MIN_QUALITY)
+            .unwrap()
+            .len() as u64;
+
+        let result = search_quality(&img, &AllowedImageFormat::Jpeg, 0).unwrap();
+
+        assert_eq!(result.err(), Some(smallest));
+    }
+
+    #[test]
+    fn search_quality_fits_target() {
+        let img = noisy_image();
+        let target = encode_with_quality(&img, &AllowedImageFormat::Jpeg, 60)
+            .unwrap()
+            .len() as u64;
+
+        let (data, quality) = search_quality(&img, &AllowedImageFormat::Jpeg, target)
+            .unwrap()
+            .unwrap();
+
+        assert!(data.len() as u64 <= target);
+        assert!((60..MAX_QUALITY).contains(&quality), "{quality}");
+    }
 }
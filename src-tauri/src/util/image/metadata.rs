@@ -0,0 +1,643 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{error::UploadError, manager::AllowedImageFormat, Up2bResult};
+
+use super::guess_format;
+
+/// 输出文件的一部分：原文件中的一段，或修改后的数据
+#[derive(Debug, PartialEq)]
+enum Part {
+    Copy { start: u64, len: u64 },
+    Data(Vec<u8>),
+}
+
+impl Part {
+    fn len(&self) -> u64 {
+        match self {
+            Part::Copy { len, .. } => *len,
+            Part::Data(d) => d.len() as u64,
+        }
+    }
+}
+
+/// 需要保留的部分，相邻的原文件片段会合并
+#[derive(Debug, Default)]
+struct Plan {
+    parts: Vec<Part>,
+    /// 是否有元数据被删除
+    stripped: bool,
+}
+
+impl Plan {
+    fn copy(&mut self, start: u64, end: u64) {
+        if let Some(Part::Copy { start: s, len }) = self.parts.last_mut() {
+            if *s + *len == start {
+                *len += end - start;
+                return;
+            }
+        }
+
+        self.parts.push(Part::Copy {
+            start,
+            len: end - start,
+        });
+    }
+
+    fn data(&mut self, data: Vec<u8>) {
+        self.parts.push(Part::Data(data));
+    }
+
+    fn skip(&mut self) {
+        self.stripped = true;
+    }
+
+    fn len(&self) -> u64 {
+        self.parts.iter().map(Part::len).sum()
+    }
+}
+
+/// 去除 JPEG、PNG 和 WebP 中的 EXIF、XMP 和 IPTC 元数据，只删除元数据所在的段，不重新编码图片。
+///
+/// EXIF 中的方向不是隐私信息，删除后图片会显示为旋转前的样子，所以保留一个只包含方向的 EXIF。
+///
+/// 只读取各段的头部，其余部分直接从原文件复制到 dst，不会将整个文件读入内存。
+/// 不支持的格式或没有元数据时返回 false，且不会创建 dst
+pub fn strip_metadata(src: &Path, dst: &Path) -> Up2bResult<bool> {
+    let mut reader = BufReader::new(File::open(src)?);
+
+    let plan = match plan(&mut reader)? {
+        Some(p) => p,
+        None => return Ok(false),
+    };
+
+    let mut writer = BufWriter::new(File::create(dst)?);
+    write_parts(&mut reader, &plan.parts, &mut writer)?;
+    writer.flush()?;
+
+    Ok(true)
+}
+
+fn plan<R: Read + Seek>(reader: &mut R) -> Up2bResult<Option<Plan>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+
+    let mut header = [0u8; 16];
+    let n = read_at(reader, 0, &mut header)?;
+
+    let plan = match guess_format(&header[..n]) {
+        Some(AllowedImageFormat::Jpeg) => plan_jpeg(reader, len)?,
+        Some(AllowedImageFormat::Png) => plan_png(reader, len)?,
+        Some(AllowedImageFormat::Webp) => plan_webp(reader, len)?,
+        _ => return Ok(None),
+    };
+
+    match plan {
+        None => Err(UploadError::Corrupted.into()),
+        Some(p) if !p.stripped => Ok(None),
+        Some(p) => Ok(Some(p)),
+    }
+}
+
+fn write_parts<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    parts: &[Part],
+    writer: &mut W,
+) -> io::Result<()> {
+    for part in parts {
+        match part {
+            Part::Copy { start, len } => {
+                reader.seek(SeekFrom::Start(*start))?;
+                io::copy(&mut reader.by_ref().take(*len), writer)?;
+            }
+            Part::Data(d) => writer.write_all(d)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// 从 pos 处读取，返回实际读取的字节数，文件结尾时可能小于 buf 的长度
+fn read_at<R: Read + Seek>(reader: &mut R, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+    reader.seek(SeekFrom::Start(pos))?;
+
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+
+    Ok(n)
+}
+
+/// 读取 n 个字节，超出文件结尾时返回 None
+fn read_exact_at<R: Read + Seek>(
+    reader: &mut R,
+    pos: u64,
+    n: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut buf = vec![0; n];
+
+    match read_at(reader, pos, &mut buf)? == n {
+        true => Ok(Some(buf)),
+        false => Ok(None),
+    }
+}
+
+/// 在 [`plan_jpeg`] 等函数中将 `io::Result<Option<T>>` 展开为 T，数据不完整时返回 `Ok(None)`
+macro_rules! read_or_none {
+    ($e:expr) => {
+        match $e? {
+            Some(v) => v,
+            None => return Ok(None),
+        }
+    };
+}
+
+fn read_u8<R: Read + Seek>(reader: &mut R, pos: u64) -> io::Result<Option<u8>> {
+    Ok(read_exact_at(reader, pos, 1)?.map(|b| b[0]))
+}
+
+fn read_u16_be<R: Read + Seek>(reader: &mut R, pos: u64) -> io::Result<Option<u64>> {
+    Ok(read_exact_at(reader, pos, 2)?.map(|b| u16::from_be_bytes([b[0], b[1]]) as u64))
+}
+
+fn read_u32_be<R: Read + Seek>(reader: &mut R, pos: u64) -> io::Result<Option<u64>> {
+    Ok(read_exact_at(reader, pos, 4)?.map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64))
+}
+
+fn read_u32_le<R: Read + Seek>(reader: &mut R, pos: u64) -> io::Result<Option<u64>> {
+    Ok(read_exact_at(reader, pos, 4)?.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u64))
+}
+
+/// EXIF 中方向标签的 id
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// 读取 EXIF 时最多读取的字节数，方向标签在 IFD0 中，通常位于开头
+const MAX_EXIF_READ: u64 = 64 * 1024;
+
+/// 读取 TIFF 格式的 EXIF 中 IFD0 的方向，无效的值返回 None
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+
+    let u16_at = |pos: usize| {
+        let b = tiff.get(pos..pos + 2)?;
+        Some(match big_endian {
+            true => u16::from_be_bytes([b[0], b[1]]),
+            false => u16::from_le_bytes([b[0], b[1]]),
+        })
+    };
+    let u32_at = |pos: usize| {
+        let b = tiff.get(pos..pos + 4)?;
+        Some(match big_endian {
+            true => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            false => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        })
+    };
+
+    if u16_at(2)? != 42 {
+        return None;
+    }
+
+    let ifd = u32_at(4)? as usize;
+
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| u16_at(*entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|o| (1..=8).contains(o))
+}
+
+/// 只包含方向的 TIFF 格式的 EXIF
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2a".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    // IFD0 中只有一个条目：标签、类型 SHORT、数量、值（左对齐）
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // 没有下一个 IFD
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+/// 读取 start 处的 EXIF 中需要保留的方向，方向为默认值 1 时不需要保留
+fn read_orientation<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    size: u64,
+) -> io::Result<Option<u16>> {
+    let mut tiff = vec![0; size.min(MAX_EXIF_READ) as usize];
+    let n = read_at(reader, start, &mut tiff)?;
+
+    Ok(exif_orientation(&tiff[..n]).filter(|o| *o != 1))
+}
+
+/// png 块的 crc，计算范围为类型和数据
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    !crc
+}
+
+/// 删除 APP1（EXIF、XMP）和 APP13（IPTC）段，保留 ICC 配置等其他段，EXIF 只保留方向
+fn plan_jpeg<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Plan>> {
+    let mut plan = Plan::default();
+    plan.copy(0, 2);
+
+    let mut pos = 2;
+
+    loop {
+        if read_or_none!(read_u8(reader, pos)) != 0xFF {
+            return Ok(None);
+        }
+
+        // 跳过填充字节
+        while read_or_none!(read_u8(reader, pos + 1)) == 0xFF {
+            pos += 1;
+        }
+
+        let marker = read_or_none!(read_u8(reader, pos + 1));
+
+        match marker {
+            // SOS 之后是压缩数据，EOI 之后的数据原样保留
+            0xDA | 0xD9 => {
+                plan.copy(pos, len);
+                return Ok(Some(plan));
+            }
+            // 没有长度的标记
+            0x01 | 0xD0..=0xD7 => {
+                plan.copy(pos, pos + 2);
+                pos += 2;
+            }
+            _ => {
+                let end = pos + 2 + read_or_none!(read_u16_be(reader, pos + 2));
+                if end > len {
+                    return Ok(None);
+                }
+
+                match marker {
+                    0xE1 => {
+                        plan.skip();
+
+                        let header = read_exact_at(reader, pos + 4, 6)?;
+                        if header.as_deref() == Some(b"Exif\0\0") {
+                            if let Some(o) = read_orientation(reader, pos + 10, end - pos - 10)? {
+                                let mut payload = b"Exif\0\0".to_vec();
+                                payload.extend(orientation_exif(o));
+
+                                let mut segment = vec![0xFF, 0xE1];
+                                segment
+                                    .extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+                                segment.extend(payload);
+                                plan.data(segment);
+                            }
+                        }
+                    }
+                    0xED => plan.skip(),
+                    _ => plan.copy(pos, end),
+                }
+
+                pos = end;
+            }
+        }
+    }
+}
+
+/// 删除 eXIf 和文本块，XMP 保存在 iTXt 中，eXIf 只保留方向
+fn plan_png<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Plan>> {
+    let mut plan = Plan::default();
+    plan.copy(0, 8);
+
+    let mut pos = 8;
+
+    while pos < len {
+        // 长度 + 类型 + 数据 + crc
+        let end = pos + 12 + read_or_none!(read_u32_be(reader, pos));
+        let kind = read_or_none!(read_exact_at(reader, pos + 4, 4));
+        if end > len {
+            return Ok(None);
+        }
+
+        match kind.as_slice() {
+            b"eXIf" => {
+                plan.skip();
+
+                if let Some(o) = read_orientation(reader, pos + 8, end - pos - 12)? {
+                    let mut chunk = b"eXIf".to_vec();
+                    chunk.extend(orientation_exif(o));
+
+                    let mut data = ((chunk.len() - 4) as u32).to_be_bytes().to_vec();
+                    data.extend_from_slice(&chunk);
+                    data.extend_from_slice(&crc32(&chunk).to_be_bytes());
+                    plan.data(data);
+                }
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" => plan.skip(),
+            _ => plan.copy(pos, end),
+        }
+
+        pos = end;
+
+        if kind == b"IEND" {
+            plan.copy(pos, len);
+            break;
+        }
+    }
+
+    Ok(Some(plan))
+}
+
+/// 删除 EXIF 和 XMP 块，并清除 VP8X 中对应的标志位，EXIF 只保留方向
+fn plan_webp<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Plan>> {
+    let riff_end = (8 + read_or_none!(read_u32_le(reader, 4))).min(len);
+
+    let mut plan = Plan::default();
+    // RIFF 头中的长度在最后修改
+    plan.data(read_or_none!(read_exact_at(reader, 0, 12)));
+
+    let mut pos = 12;
+    // VP8X 在 EXIF 之前，是否保留了 EXIF 需要在最后设置
+    let mut vp8x = None;
+    let mut has_exif = false;
+
+    while pos + 8 <= riff_end {
+        let kind = read_or_none!(read_exact_at(reader, pos, 4));
+        let size = read_or_none!(read_u32_le(reader, pos + 4));
+        // 块的数据长度为奇数时有一个填充字节
+        let end = (pos + 8 + size + size % 2).min(riff_end);
+
+        match kind.as_slice() {
+            b"EXIF" => {
+                plan.skip();
+
+                if let Some(o) = read_orientation(reader, pos + 8, size.min(end - pos - 8))? {
+                    let exif = orientation_exif(o);
+
+                    let mut chunk = b"EXIF".to_vec();
+                    chunk.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+                    chunk.extend(exif);
+                    plan.data(chunk);
+                    has_exif = true;
+                }
+            }
+            b"XMP " => plan.skip(),
+            b"VP8X" if end - pos > 8 => {
+                vp8x = Some(plan.parts.len());
+                plan.data(read_or_none!(read_exact_at(
+                    reader,
+                    pos,
+                    (end - pos) as usize
+                )));
+            }
+            _ => plan.copy(pos, end),
+        }
+
+        pos = end;
+    }
+
+    if let Some(Part::Data(chunk)) = vp8x.and_then(|i| plan.parts.get_mut(i)) {
+        chunk[8] &= !0x04;
+        if !has_exif {
+            chunk[8] &= !0x08;
+        }
+    }
+
+    let riff_size = (plan.len() - 8) as u32;
+    if let Some(Part::Data(header)) = plan.parts.first_mut() {
+        header[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    }
+
+    Ok(Some(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn strip(data: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Cursor::new(data);
+        let plan = plan(&mut reader).unwrap()?;
+
+        let mut out = Vec::new();
+        write_parts(&mut reader, &plan.parts, &mut out).unwrap();
+
+        Some(out)
+    }
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn png_chunk(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = (payload.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(payload);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn webp_chunk(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&body);
+        data
+    }
+
+    #[test]
+    fn strips_jpeg_exif_and_iptc() {
+        let app0 = jpeg_segment(0xE0, b"JFIF\0\x01\x01");
+        let sos = [0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+
+        let image = [&[0xFF, 0xD8][..], &app0, &sos].concat();
+        let data = [
+            &[0xFF, 0xD8][..],
+            &jpeg_segment(0xE1, b"Exif\0\0MM"),
+            &app0,
+            &jpeg_segment(0xED, b"Photoshop 3.0\0"),
+            &sos,
+        ]
+        .concat();
+
+        assert_eq!(strip(&data), Some(image.clone()));
+        assert_eq!(strip(&image), None);
+    }
+
+    #[test]
+    fn strips_png_text_chunks() {
+        let signature = b"\x89PNG\r\n\x1a\n";
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        let iend = png_chunk(b"IEND", &[]);
+
+        let image = [&signature[..], &ihdr, &iend].concat();
+        let data = [
+            &signature[..],
+            &ihdr,
+            &png_chunk(b"tEXt", b"Comment\0hello"),
+            &png_chunk(b"eXIf", b"MM\0*"),
+            &iend,
+        ]
+        .concat();
+
+        assert_eq!(strip(&data), Some(image.clone()));
+        assert_eq!(strip(&image), None);
+    }
+
+    #[test]
+    fn strips_webp_chunks_and_flags() {
+        let mut vp8x = vec![0; 10];
+        vp8x[0] = 0x08 | 0x04 | 0x10;
+        let vp8l = webp_chunk(b"VP8L", b"abc");
+
+        let data = webp(&[
+            webp_chunk(b"VP8X", &vp8x),
+            vp8l.clone(),
+            webp_chunk(b"EXIF", b"MM\0*\0"),
+            webp_chunk(b"XMP ", b"<x/>"),
+        ]);
+
+        let mut cleared = vp8x.clone();
+        cleared[0] = 0x10;
+        let image = webp(&[webp_chunk(b"VP8X", &cleared), vp8l]);
+
+        assert_eq!(strip(&data), Some(image));
+    }
+
+    #[test]
+    fn reads_orientation_in_both_byte_orders() {
+        let big = orientation_exif(6);
+        assert_eq!(exif_orientation(&big), Some(6));
+
+        let little = [
+            b'I', b'I', 42, 0, 8, 0, 0, 0, 2, 0, // 两个条目
+            0x0F, 0x01, 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, // Make
+            0x12, 0x01, 3, 0, 1, 0, 0, 0, 8, 0, 0, 0, // Orientation
+            0, 0, 0, 0,
+        ];
+        assert_eq!(exif_orientation(&little), Some(8));
+
+        assert_eq!(exif_orientation(&orientation_exif(9)), None);
+        assert_eq!(exif_orientation(b"MM\0\x2a"), None);
+    }
+
+    #[test]
+    fn keeps_jpeg_orientation() {
+        let exif = |o: u16| {
+            let mut exif = b"Exif\0\0".to_vec();
+            // 包含其他标签的 EXIF，IFD0 中方向之前还有一个条目
+            exif.extend_from_slice(b"MM\0\x2a\0\0\0\x08\0\x02");
+            exif.extend_from_slice(&[0x01, 0x0F, 0, 2, 0, 0, 0, 4, b'u', b'p', b'2', 0]);
+            exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+            exif.extend_from_slice(&o.to_be_bytes());
+            exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+            exif
+        };
+        let sos = [0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9];
+
+        let data = [&[0xFF, 0xD8][..], &jpeg_segment(0xE1, &exif(6)), &sos].concat();
+        let minimal = [&b"Exif\0\0"[..], &orientation_exif(6)].concat();
+        assert_eq!(
+            strip(&data),
+            Some([&[0xFF, 0xD8][..], &jpeg_segment(0xE1, &minimal), &sos].concat())
+        );
+
+        // 默认方向不需要保留
+        let data = [&[0xFF, 0xD8][..], &jpeg_segment(0xE1, &exif(1)), &sos].concat();
+        assert_eq!(strip(&data), Some([&[0xFF, 0xD8][..], &sos].concat()));
+    }
+
+    #[test]
+    fn keeps_png_orientation() {
+        let signature = b"\x89PNG\r\n\x1a\n";
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        let iend = png_chunk(b"IEND", &[]);
+
+        let mut exif = orientation_exif(3);
+        exif.extend_from_slice(b"padding");
+        let data = [&signature[..], &ihdr, &png_chunk(b"eXIf", &exif), &iend].concat();
+
+        let stripped = strip(&data).unwrap();
+        let chunk = &stripped[8 + ihdr.len()..stripped.len() - iend.len()];
+
+        assert_eq!(&chunk[4..8], b"eXIf");
+        assert_eq!(&chunk[8..chunk.len() - 4], orientation_exif(3).as_slice());
+        assert_eq!(
+            &chunk[chunk.len() - 4..],
+            crc32(&chunk[4..chunk.len() - 4]).to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn keeps_webp_orientation_flag() {
+        let mut vp8x = vec![0; 10];
+        vp8x[0] = 0x08 | 0x04;
+        let vp8l = webp_chunk(b"VP8L", b"abc");
+
+        let data = webp(&[
+            webp_chunk(b"VP8X", &vp8x),
+            vp8l.clone(),
+            webp_chunk(b"EXIF", &[orientation_exif(6), vec![0; 9]].concat()),
+            webp_chunk(b"XMP ", b"<x/>"),
+        ]);
+
+        let mut cleared = vp8x.clone();
+        cleared[0] = 0x08;
+        let image = webp(&[
+            webp_chunk(b"VP8X", &cleared),
+            vp8l,
+            webp_chunk(b"EXIF", &orientation_exif(6)),
+        ]);
+
+        assert_eq!(strip(&data), Some(image));
+    }
+
+    #[test]
+    fn crc32_matches_png_iend() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn truncated_segment_is_corrupted() {
+        let data = [&[0xFF, 0xD8][..], &[0xFF, 0xE1, 0x10, 0x00, 0x00]].concat();
+
+        assert!(plan(&mut Cursor::new(data)).is_err());
+    }
+}
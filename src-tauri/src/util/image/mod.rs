@@ -1,11 +1,256 @@
 #[cfg(feature = "compress")]
 pub mod compress;
+pub mod metadata;
+#[cfg(feature = "compress")]
+mod png;
+#[cfg(feature = "compress")]
+pub mod thumbnail;
+#[cfg(feature = "compress")]
+pub mod watermark;
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ConfigError, manager::AllowedImageFormat, util::time::now, Up2bResult};
+
+lazy_static! {
+    pub static ref TEMP_DIR: PathBuf = std::env::temp_dir().join("up2b-".to_owned() + &now().unwrap().as_secs().to_string()); // 不能重名，加时间后缀
+}
+
+/// 解码图片时的限制，避免过大的图片导致内存溢出
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DecodeLimits {
+    /// 解码时的最大内存占用，单位为 MB，为 0 时不限制
+    #[serde(default = "default_max_alloc")]
+    pub max_alloc: u64,
+    /// 最大宽度，单位为像素
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_width: Option<u32>,
+    /// 最大高度，单位为像素
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<u32>,
+}
+
+fn default_max_alloc() -> u64 {
+    512
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_alloc: default_max_alloc(),
+            max_width: None,
+            max_height: None,
+        }
+    }
+}
+
+/// 上传前的图片优化，需要启用 compress 特性，与超过体积限制时的压缩无关
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OptimizeConfig {
+    /// 转换后的格式，只支持 JPEG、WEBP、AVIF 和 PNG，PNG 会按质量减少颜色数量
+    #[serde(default = "default_optimize_format")]
+    pub format: AllowedImageFormat,
+    /// 编码质量，1-100
+    #[serde(default = "default_optimize_quality")]
+    pub quality: u8,
+    /// 最大宽高，超过时等比缩小
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_dimension: Option<u32>,
+}
+
+impl OptimizeConfig {
+    /// 检查转换后的格式，GIF 和 BMP 无法作为优化的目标格式
+    pub fn validate(&self) -> Up2bResult<()> {
+        match self.format {
+            AllowedImageFormat::Jpeg
+            | AllowedImageFormat::Webp
+            | AllowedImageFormat::Avif
+            | AllowedImageFormat::Png => Ok(()),
+            AllowedImageFormat::Gif | AllowedImageFormat::Bmp => {
+                Err(ConfigError::OptimizeFormat(format!("{:?}", self.format).to_uppercase()).into())
+            }
+        }
+    }
+}
+
+fn default_optimize_format() -> AllowedImageFormat {
+    AllowedImageFormat::Webp
+}
+
+fn default_optimize_quality() -> u8 {
+    80
+}
+
+/// 为没有缩略图的图床生成缩略图，需要启用 compress 特性
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThumbnailConfig {
+    /// 最长边，单位为像素
+    #[serde(default = "default_thumbnail_max_edge")]
+    pub max_edge: u32,
+    /// 只支持 JPEG、WEBP 和 AVIF
+    #[serde(default = "default_optimize_format")]
+    pub format: AllowedImageFormat,
+    #[serde(default = "default_thumbnail_quality")]
+    pub quality: u8,
+    /// 为 false 时只保存在本地，上传缩略图失败时也会保存在本地
+    #[serde(default = "default_thumbnail_upload")]
+    pub upload: bool,
+}
+
+fn default_thumbnail_max_edge() -> u32 {
+    400
+}
+
+fn default_thumbnail_quality() -> u8 {
+    75
+}
+
+fn default_thumbnail_upload() -> bool {
+    true
+}
+
+/// 水印的内容
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "UPPERCASE")]
+pub enum WatermarkKind {
+    Text {
+        text: String,
+        /// 字体文件路径，支持 ttf 和 otf
+        font: PathBuf,
+        #[serde(default = "default_watermark_color")]
+        color: [u8; 3],
+    },
+    /// png 图标
+    Image { path: PathBuf },
+}
+
+fn default_watermark_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+/// 上传前添加水印，需要启用 compress 特性
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatermarkConfig {
+    pub kind: WatermarkKind,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+    /// 不透明度，0-1
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+    /// 水印与图片边缘的距离，单位为像素
+    #[serde(default = "default_watermark_margin")]
+    pub margin: u32,
+    /// 水印宽度与图片宽度的比例
+    #[serde(default = "default_watermark_scale")]
+    pub scale: f32,
+    /// 宽或高小于此值的图片不添加水印
+    #[serde(default)]
+    pub min_width: u32,
+    #[serde(default)]
+    pub min_height: u32,
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.5
+}
+
+fn default_watermark_margin() -> u32 {
+    16
+}
+
+fn default_watermark_scale() -> f32 {
+    0.2
+}
+
+/// 生成临时图片的路径，每张图片单独使用一个随机命名的目录，既保留文件名又不会与同名图片冲突
+pub async fn temp_image_path(filename: &str) -> Up2bResult<PathBuf> {
+    let dir = TEMP_DIR.join(uuid::Uuid::new_v4().simple().to_string());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    Ok(dir.join(filename))
+}
+
+/// 删除临时图片及其所在目录，不在临时目录中的图片不会被删除
+pub async fn remove_temp_image(path: &Path) {
+    let dir = match path.parent() {
+        Some(d) if d.starts_with(TEMP_DIR.as_path()) && d != TEMP_DIR.as_path() => d,
+        _ => return,
+    };
+
+    if let Err(e) = tokio::fs::remove_dir_all(dir).await {
+        warn!("删除临时图片失败：{:?}, {}", dir, e);
+    }
+}
+
+/// 通过文件头判断图片格式，不是支持的图片格式时返回 None
+pub fn guess_format(data: &[u8]) -> Option<AllowedImageFormat> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(AllowedImageFormat::Jpeg);
+    }
+
+    if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some(AllowedImageFormat::Png);
+    }
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(AllowedImageFormat::Gif);
+    }
+
+    if data.starts_with(b"BM") {
+        return Some(AllowedImageFormat::Bmp);
+    }
+
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some(AllowedImageFormat::Webp);
+    }
+
+    if data.len() >= 12 && &data[4..8] == b"ftyp" && matches!(&data[8..12], b"avif" | b"avis") {
+        return Some(AllowedImageFormat::Avif);
+    }
+
+    None
+}
+
+/// 只读取文件头判断静态图片的格式，动图（gif 和动态 webp）返回 None
+#[cfg(feature = "compress")]
+pub async fn guess_still_format(path: &Path) -> Up2bResult<Option<AllowedImageFormat>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut header = [0u8; 21];
+    let n = tokio::fs::File::open(path).await?.read(&mut header).await?;
+    let header = &header[..n];
+
+    Ok(match guess_format(header) {
+        Some(AllowedImageFormat::Gif) => None,
+        Some(AllowedImageFormat::Webp) if is_animated_webp(header) => None,
+        f => f,
+    })
+}
+
+/// 动态 webp 的第一个块为 VP8X，且设置了动画标志
+#[cfg(feature = "compress")]
+fn is_animated_webp(header: &[u8]) -> bool {
+    header.len() > 20 && &header[12..16] == b"VP8X" && header[20] & 0x02 != 0
+}
 
 pub fn guess_mime_type_by_ext(filename: &str) -> String {
     let ext = filename.rsplitn(2, '.').last();
 
     match ext {
-        None => return "image/jpeg".to_owned(),
+        None => "image/jpeg".to_owned(),
         Some(s) => {
             let lower = s.to_lowercase();
             match lower.as_str() {
@@ -15,3 +260,71 @@ pub fn guess_mime_type_by_ext(filename: &str) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn same_name_temp_images_do_not_collide() {
+        let a = temp_image_path("a.png").await.unwrap();
+        let b = temp_image_path("a.png").await.unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.file_name(), b.file_name());
+
+        tokio::fs::write(&a, b"png").await.unwrap();
+        remove_temp_image(&a).await;
+        assert!(!a.parent().unwrap().exists());
+        assert!(b.parent().unwrap().exists());
+
+        remove_temp_image(&b).await;
+    }
+
+    #[test]
+    fn optimize_format_is_validated() {
+        let config = |format: &str| -> OptimizeConfig {
+            toml::from_str(&format!("format = \"{}\"", format)).unwrap()
+        };
+
+        for format in ["JPEG", "WEBP", "AVIF", "PNG"] {
+            config(format).validate().unwrap();
+        }
+
+        for format in ["GIF", "BMP"] {
+            let e = config(format).validate().unwrap_err();
+            assert!(
+                matches!(e, crate::Up2bError::Config(ConfigError::OptimizeFormat(ref f)) if f == format),
+                "{e:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "compress")]
+    #[tokio::test]
+    async fn animated_images_have_no_still_format() {
+        let webp = |chunk: &[u8], flags: u8| {
+            let mut data = b"RIFF\0\0\0\0WEBP".to_vec();
+            data.extend_from_slice(chunk);
+            data.extend_from_slice(&[10, 0, 0, 0, flags]);
+            data
+        };
+
+        for (name, data, format) in [
+            ("a.gif", b"GIF89a".to_vec(), None),
+            ("a.webp", webp(b"VP8X", 0x02), None),
+            (
+                "b.webp",
+                webp(b"VP8X", 0x10),
+                Some(AllowedImageFormat::Webp),
+            ),
+            (
+                "c.webp",
+                webp(b"VP8 ", 0x02),
+                Some(AllowedImageFormat::Webp),
+            ),
+        ] {
+            let path = crate::http::mock::temp_file(name, &data).await;
+            assert_eq!(guess_still_format(&path).await.unwrap(), format, "{name}");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::Cursor;
+
+use color_quant::NeuQuant;
+use image::{DynamicImage, ImageFormat};
+
+use crate::{Up2bError, Up2bResult};
+
+/// oxipng 的优化等级，等级越高越慢
+const OXIPNG_PRESET: u8 = 2;
+
+fn oxipng(data: &[u8]) -> Up2bResult<Vec<u8>> {
+    oxipng::optimize_from_memory(data, &oxipng::Options::from_preset(OXIPNG_PRESET))
+        .map_err(|e| Up2bError::Other(format!("优化 png 失败：{}", e)))
+}
+
+/// 无损优化：重新编码后用 oxipng 选择最优的过滤器和压缩参数
+pub(super) fn optimize_lossless(img: &DynamicImage) -> Up2bResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)?;
+
+    oxipng(&buffer)
+}
+
+/// NeuQuant 的采样因子，1-30，越小质量越高、越慢
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// 质量对应的调色板颜色数，2-256
+fn palette_size(quality: u8) -> usize {
+    (usize::from(quality.min(100)) * 256 / 100).clamp(2, 256)
+}
+
+/// 有损优化：用 NeuQuant 将颜色减少到 256 色以内，保存为索引色 png 后再无损优化。
+///
+/// quality 为 0-100，决定调色板的颜色数
+pub(super) fn quantize(img: &DynamicImage, quality: u8) -> Up2bResult<Vec<u8>> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    let quant = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, palette_size(quality), rgba.as_raw());
+
+    let indices: Vec<u8> = rgba
+        .as_raw()
+        .chunks_exact(4)
+        .map(|p| quant.index_of(p) as u8)
+        .collect();
+
+    let palette = quant.color_map_rgba();
+    let rgb: Vec<u8> = palette
+        .chunks_exact(4)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    let alpha: Vec<u8> = palette.chunks_exact(4).map(|c| c[3]).collect();
+
+    let mut buffer = Vec::new();
+    {
+        let map_err = |e: ::png::EncodingError| Up2bError::Other(format!("编码 png 失败：{}", e));
+
+        let mut encoder = ::png::Encoder::new(&mut buffer, width, height);
+        encoder.set_color(::png::ColorType::Indexed);
+        encoder.set_depth(::png::BitDepth::Eight);
+        encoder.set_palette(rgb);
+        encoder.set_trns(alpha);
+
+        let mut writer = encoder.write_header().map_err(map_err)?;
+        writer.write_image_data(&indices).map_err(map_err)?;
+        writer.finish().map_err(map_err)?;
+    }
+
+    oxipng(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn palette_size_is_bounded() {
+        assert_eq!(palette_size(0), 2);
+        assert_eq!(palette_size(50), 128);
+        assert_eq!(palette_size(100), 256);
+        assert_eq!(palette_size(255), 256);
+    }
+
+    #[test]
+    fn quantized_png_keeps_dimensions() {
+        let data = quantize(&gradient(), 30).unwrap();
+        let decoded = image::load_from_memory_with_format(&data, ImageFormat::Png).unwrap();
+
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+    }
+
+    #[test]
+    fn lossless_png_keeps_pixels() {
+        let img = gradient();
+        let data = optimize_lossless(&img).unwrap();
+        let decoded = image::load_from_memory_with_format(&data, ImageFormat::Png).unwrap();
+
+        assert_eq!(decoded.to_rgba8(), img.to_rgba8());
+    }
+}
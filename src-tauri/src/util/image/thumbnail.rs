@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use crate::{manager::AllowedImageFormat, Up2bResult};
+
+use super::{
+    compress::{decode_with, encode},
+    guess_still_format, DecodeLimits, OptimizeConfig, ThumbnailConfig,
+};
+
+/// 生成缩略图，图片本身不超过最长边时返回 None。
+///
+/// 动图和 avif 不生成缩略图
+pub async fn thumbnail(
+    image_path: &Path,
+    config: &ThumbnailConfig,
+    limits: &DecodeLimits,
+) -> Up2bResult<Option<Vec<u8>>> {
+    if matches!(
+        guess_still_format(image_path).await?,
+        Some(AllowedImageFormat::Avif) | None
+    ) {
+        return Ok(None);
+    }
+
+    let max_edge = config.max_edge;
+    let options = OptimizeConfig {
+        format: config.format.clone(),
+        quality: config.quality,
+        max_dimension: Some(max_edge),
+    };
+
+    decode_with(image_path, limits, move |img| {
+        if img.width() <= max_edge && img.height() <= max_edge {
+            return Ok(None);
+        }
+
+        Ok(Some(encode(img, &options)?))
+    })
+    .await
+}
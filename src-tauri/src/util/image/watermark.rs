@@ -0,0 +1,175 @@
+use std::{io::Cursor, path::Path};
+
+use ab_glyph::{FontVec, PxScale};
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, ImageFormat, Rgba, RgbaImage,
+};
+use imageproc::drawing::{draw_text_mut, text_size};
+
+use crate::{manager::AllowedImageFormat, Up2bError, Up2bResult};
+
+use super::{
+    compress::{blocking, decode_with, encode_with_quality},
+    guess_still_format, DecodeLimits, WatermarkConfig, WatermarkKind, WatermarkPosition,
+};
+
+/// 文字水印先按此字号绘制，再缩放到目标宽度
+const TEXT_RENDER_SIZE: f32 = 64.0;
+
+/// 带水印的 jpeg 和 webp 的编码质量
+const QUALITY: u8 = 90;
+
+fn text_mark(text: &str, font: &[u8], color: [u8; 3]) -> Up2bResult<RgbaImage> {
+    let font = FontVec::try_from_vec(font.to_vec())
+        .map_err(|e| Up2bError::Other(format!("无法读取水印字体：{}", e)))?;
+
+    let scale = PxScale::from(TEXT_RENDER_SIZE);
+    let (width, height) = text_size(scale, &font, text);
+
+    // 在透明图层上绘制，透明度之后统一处理
+    let mut mark = RgbaImage::new(width.max(1), height.max(1));
+    draw_text_mut(
+        &mut mark,
+        Rgba([color[0], color[1], color[2], 255]),
+        0,
+        0,
+        scale,
+        &font,
+        text,
+    );
+
+    Ok(mark)
+}
+
+/// 左上角的坐标
+fn position(base: (u32, u32), mark: (u32, u32), config: &WatermarkConfig) -> (i64, i64) {
+    let margin = config.margin as i64;
+    let (bw, bh) = (base.0 as i64, base.1 as i64);
+    let (mw, mh) = (mark.0 as i64, mark.1 as i64);
+
+    match config.position {
+        WatermarkPosition::TopLeft => (margin, margin),
+        WatermarkPosition::TopRight => (bw - mw - margin, margin),
+        WatermarkPosition::BottomLeft => (margin, bh - mh - margin),
+        WatermarkPosition::BottomRight => (bw - mw - margin, bh - mh - margin),
+        WatermarkPosition::Center => ((bw - mw) / 2, (bh - mh) / 2),
+    }
+}
+
+fn encode(img: DynamicImage, format: &AllowedImageFormat) -> Up2bResult<Vec<u8>> {
+    match format {
+        // 与压缩使用相同的编码，webp 为有损编码
+        AllowedImageFormat::Jpeg | AllowedImageFormat::Webp => {
+            encode_with_quality(&img, format, QUALITY)
+        }
+        // png 保持无损，不使用压缩时的量化
+        AllowedImageFormat::Png => {
+            let mut buffer = Vec::new();
+            img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)?;
+            Ok(buffer)
+        }
+        AllowedImageFormat::Bmp => {
+            let mut buffer = Vec::new();
+            img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Bmp)?;
+            Ok(buffer)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn stamp(
+    img: DynamicImage,
+    format: AllowedImageFormat,
+    config: &WatermarkConfig,
+    mark: RgbaImage,
+) -> Up2bResult<Option<Vec<u8>>> {
+    if img.width() < config.min_width || img.height() < config.min_height {
+        debug!("图片尺寸小于水印的最小尺寸，不添加水印");
+        return Ok(None);
+    }
+
+    // 按比例缩放水印
+    let width = ((img.width() as f32 * config.scale.clamp(0.01, 1.0)) as u32).max(1);
+    let height = ((mark.height() as f32 * width as f32 / mark.width() as f32) as u32).max(1);
+    let mut mark = imageops::resize(&mark, width, height, FilterType::Lanczos3);
+
+    let opacity = config.opacity.clamp(0.0, 1.0);
+    for pixel in mark.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity) as u8;
+    }
+
+    let mut base = img.to_rgba8();
+    let (x, y) = position(base.dimensions(), mark.dimensions(), config);
+    imageops::overlay(&mut base, &mark, x, y);
+
+    Ok(Some(encode(DynamicImage::ImageRgba8(base), &format)?))
+}
+
+/// 给图片添加水印，保持原图的格式。
+///
+/// 图片小于配置的最小尺寸时返回 None，动图和 avif 也不添加水印
+pub async fn watermark(
+    image_path: &Path,
+    config: &WatermarkConfig,
+    limits: &DecodeLimits,
+) -> Up2bResult<Option<Vec<u8>>> {
+    let format = match guess_still_format(image_path).await? {
+        Some(
+            f @ (AllowedImageFormat::Jpeg
+            | AllowedImageFormat::Png
+            | AllowedImageFormat::Webp
+            | AllowedImageFormat::Bmp),
+        ) => f,
+        _ => return Ok(None),
+    };
+
+    // 读取和绘制水印也比较耗时，同样在阻塞线程中执行
+    let mark = match &config.kind {
+        WatermarkKind::Text { text, font, color } => {
+            let (text, color) = (text.clone(), *color);
+            let font = tokio::fs::read(font).await?;
+            blocking(move || text_mark(&text, &font, color)).await?
+        }
+        WatermarkKind::Image { path } => {
+            let path = path.clone();
+            blocking(move || Ok(image::open(path)?.to_rgba8())).await?
+        }
+    };
+
+    let config = config.clone();
+
+    decode_with(image_path, limits, move |img| {
+        stamp(img, format, &config, mark)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webp_uses_lossy_encoding() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([200, 0, 0, 255])));
+        let data = encode(img, &AllowedImageFormat::Webp).unwrap();
+
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(&data[8..12], b"WEBP");
+        // 无损 webp 的块为 VP8L
+        assert!(data.windows(4).any(|w| w == b"VP8 "));
+    }
+
+    #[tokio::test]
+    async fn animated_webp_is_skipped() {
+        let mut data = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        data.extend_from_slice(&[10, 0, 0, 0, 0x02]);
+        let path = crate::http::mock::temp_file("a.webp", &data).await;
+
+        let config: WatermarkConfig =
+            toml::from_str(r#"kind = { type = "IMAGE", path = "mark.png" }"#).unwrap();
+
+        let result = watermark(&path, &config, &DecodeLimits::default()).await;
+        assert!(result.unwrap().is_none());
+    }
+}
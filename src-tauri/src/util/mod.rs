@@ -1,2 +1,6 @@
+pub mod hash;
 pub mod image;
+pub mod naming;
+pub mod template;
 pub mod time;
+pub mod url;
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use crate::{
+    error::PathError,
+    history::file_hash,
+    util::time::{now, now_local},
+    Up2bError, Up2bResult,
+};
+
+/// Git、S3 和本地目录图床默认的文件名模板，与原来的命名方式相同
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}_{timestamp}.{ext}";
+
+lazy_static! {
+    static ref TOKEN: Regex = Regex::new(r"\{(\w+)(?::(\d+))?\}").unwrap();
+}
+
+/// 根据模板生成上传后的文件名（可以包含目录），可用的占位符：
+///
+/// - `{year}`、`{month}`、`{day}`、`{hour}`、`{minute}`、`{second}`：上传时间
+/// - `{timestamp}`：毫秒时间戳
+/// - `{hash}`、`{hash:8}`：文件内容的 sha256，可指定长度
+/// - `{uuid}`：随机 uuid
+/// - `{stem}`、`{ext}`、`{filename}`：原文件名（不含扩展名）、扩展名、原文件名
+///
+/// image_path 应为压缩或转换格式后实际上传的文件，使扩展名与上传的图片格式一致。
+/// 未知的占位符保持原样
+pub async fn render_file_name(template: &str, image_path: &Path) -> Up2bResult<String> {
+    let filename = match image_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) => (stem.to_owned(), ext.to_owned()),
+        None => return Err(PathError::NotFile.into()),
+    };
+
+    // 只有用到时才读取文件计算哈希
+    let hash = if template.contains("{hash") {
+        file_hash(image_path).await?.0
+    } else {
+        String::new()
+    };
+
+    let timestamp = now()?.as_millis();
+
+    let datetime = now_local();
+
+    let name = TOKEN.replace_all(template, |c: &Captures| {
+        let len = c.get(2).and_then(|l| l.as_str().parse::<usize>().ok());
+
+        match &c[1] {
+            "year" => datetime.year().to_string(),
+            "month" => format!("{:02}", u8::from(datetime.month())),
+            "day" => format!("{:02}", datetime.day()),
+            "hour" => format!("{:02}", datetime.hour()),
+            "minute" => format!("{:02}", datetime.minute()),
+            "second" => format!("{:02}", datetime.second()),
+            "timestamp" => timestamp.to_string(),
+            "hash" => hash[..len.unwrap_or(hash.len()).min(hash.len())].to_owned(),
+            "uuid" => uuid::Uuid::new_v4().to_string(),
+            "stem" => stem.clone(),
+            "ext" => ext.clone(),
+            "filename" => filename.clone(),
+            _ => c[0].to_owned(),
+        }
+    });
+
+    // 不允许跳出保存目录
+    let parts: Vec<&str> = name
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+
+    if parts.is_empty() || parts.contains(&"..") {
+        return Err(Up2bError::Other(format!("无效的文件名模板：{}", template)));
+    }
+
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock;
+
+    #[tokio::test]
+    async fn renders_file_name_placeholders() {
+        let path = mock::temp_file("photo.webp", b"up2b").await;
+
+        let name = render_file_name("{stem}/{filename}/{ext}/{hash:8}/{unknown}", &path)
+            .await
+            .unwrap();
+
+        let hash = file_hash(&path).await.unwrap().0;
+        assert_eq!(
+            name,
+            format!("photo/photo.webp/webp/{}/{{unknown}}", &hash[..8])
+        );
+
+        let name = render_file_name("{hash:100}", &path).await.unwrap();
+        assert_eq!(name, hash);
+    }
+
+    #[tokio::test]
+    async fn renders_local_date() {
+        let path = mock::temp_file("a.png", b"up2b").await;
+
+        let name = render_file_name("{year}/{month}/{day}/{hour}{minute}{second}", &path)
+            .await
+            .unwrap();
+
+        let parts: Vec<&str> = name.split('/').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], now_local().year().to_string());
+        assert_eq!(parts[1].len(), 2);
+        assert_eq!(parts[3].len(), 6);
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_outside_directory() {
+        let path = mock::temp_file("a.png", b"up2b").await;
+
+        assert!(render_file_name("../{filename}", &path).await.is_err());
+        assert!(render_file_name("/", &path).await.is_err());
+        assert_eq!(
+            render_file_name("/./a//{filename}", &path).await.unwrap(),
+            "a/a.png"
+        );
+    }
+}
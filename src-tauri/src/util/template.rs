@@ -0,0 +1,281 @@
+use std::{collections::HashMap, path::Path};
+
+use regex::{Captures, Regex};
+use serde::Serialize;
+
+use crate::manager::ImageItem;
+
+/// 内置的链接模板，配置中同名的模板会覆盖内置模板
+pub const BUILTIN_LINK_TEMPLATES: [(&str, &str); 5] = [
+    ("url", "{url}"),
+    ("markdown", "![{alt}]({url})"),
+    (
+        "html",
+        r#"<img src="{url}" alt="{alt}" width="{width}" height="{height}">"#,
+    ),
+    ("bbcode", "[img]{url}[/img]"),
+    ("rst", ".. image:: {url}\n   :alt: {alt}"),
+];
+
+lazy_static! {
+    /// width、height 属性整体匹配，以便在没有尺寸时省略
+    static ref PLACEHOLDER_RE: Regex = Regex::new(
+        r#"\s+(?:width|height)="\{(?P<size>width|height)\}"|\{(?P<name>url|thumb|filename|width|height|alt)\}"#
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LinkTemplate {
+    pub name: String,
+    pub template: String,
+    pub builtin: bool,
+}
+
+/// 合并内置模板和配置中的模板，内置模板在前
+pub fn link_templates(custom: &HashMap<String, String>) -> Vec<LinkTemplate> {
+    let mut templates: Vec<LinkTemplate> = BUILTIN_LINK_TEMPLATES
+        .iter()
+        .map(|(name, template)| LinkTemplate {
+            name: name.to_string(),
+            template: custom
+                .get(*name)
+                .cloned()
+                .unwrap_or_else(|| template.to_string()),
+            builtin: true,
+        })
+        .collect();
+
+    let mut names: Vec<&String> = custom
+        .keys()
+        .filter(|k| !BUILTIN_LINK_TEMPLATES.iter().any(|(n, _)| n == k))
+        .collect();
+    names.sort();
+
+    for name in names {
+        templates.push(LinkTemplate {
+            name: name.clone(),
+            template: custom[name].clone(),
+            builtin: false,
+        });
+    }
+
+    templates
+}
+
+/// 按名称查找模板，优先使用配置中的模板
+pub fn find_link_template(custom: &HashMap<String, String>, name: &str) -> Option<String> {
+    if let Some(t) = custom.get(name) {
+        return Some(t.clone());
+    }
+
+    BUILTIN_LINK_TEMPLATES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, t)| t.to_string())
+}
+
+/// 渲染链接模板时可用的占位符
+#[derive(Debug, Default)]
+pub struct LinkContext {
+    pub url: String,
+    /// 没有缩略图时使用原图链接
+    pub thumb: String,
+    pub filename: String,
+    pub width: String,
+    pub height: String,
+    /// 默认为不含扩展名的文件名
+    pub alt: String,
+}
+
+impl LinkContext {
+    /// image_path 为上传前的本地图片，用于获取文件名和图片尺寸，为 None 时使用链接中的文件名
+    pub fn new(image: &ImageItem, image_path: Option<&Path>, alt: Option<&str>) -> Self {
+        let filename = match image_path.and_then(|p| p.file_name()) {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => image
+                .url
+                .split(['?', '#'])
+                .next()
+                .and_then(|u| u.rsplit('/').next())
+                .unwrap_or_default()
+                .to_owned(),
+        };
+
+        let (width, height) = match image_path.map(imagesize::size) {
+            Some(Ok(size)) => (size.width.to_string(), size.height.to_string()),
+            Some(Err(e)) => {
+                warn!("无法获取图片尺寸：{:?}, {}", image_path, e);
+                (String::new(), String::new())
+            }
+            None => (String::new(), String::new()),
+        };
+
+        let alt = match alt {
+            Some(a) => a.to_owned(),
+            None => file_stem(&filename).to_owned(),
+        };
+
+        Self {
+            url: image.url.clone(),
+            thumb: image.thumb().unwrap_or(&image.url).to_owned(),
+            filename,
+            width,
+            height,
+            alt,
+        }
+    }
+
+    fn value(&self, name: &str) -> &str {
+        match name {
+            "url" => &self.url,
+            "thumb" => &self.thumb,
+            "filename" => &self.filename,
+            "width" => &self.width,
+            "height" => &self.height,
+            "alt" => &self.alt,
+            _ => unreachable!("未知的占位符：{}", name),
+        }
+    }
+}
+
+/// 替换模板中的占位符：{url}、{thumb}、{filename}、{width}、{height}、{alt}
+///
+/// 只替换一遍，值中的占位符不会被再次替换。html 模板中的值会被转义，
+/// 没有尺寸时省略 width 和 height 属性
+pub fn render(template: &str, context: &LinkContext) -> String {
+    let html = is_html(template);
+
+    PLACEHOLDER_RE
+        .replace_all(template, |caps: &Captures| {
+            let escape = |value: &str| {
+                if html {
+                    escape_html(value)
+                } else {
+                    value.to_owned()
+                }
+            };
+
+            match caps.name("size") {
+                Some(size) => {
+                    let value = context.value(size.as_str());
+                    if html && value.is_empty() {
+                        return String::new();
+                    }
+
+                    caps[0].replacen(&format!("{{{}}}", size.as_str()), &escape(value), 1)
+                }
+                None => escape(context.value(&caps["name"])),
+            }
+        })
+        .into_owned()
+}
+
+fn is_html(template: &str) -> bool {
+    template.trim_start().starts_with('<')
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// 不含扩展名的文件名
+pub fn file_stem(filename: &str) -> &str {
+    match filename.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => filename,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> LinkContext {
+        LinkContext {
+            url: "https://example.com/a.png?x=1&y=2".to_owned(),
+            thumb: "https://example.com/a_thumb.png".to_owned(),
+            filename: "a.png".to_owned(),
+            width: "640".to_owned(),
+            height: "480".to_owned(),
+            alt: "a".to_owned(),
+        }
+    }
+
+    #[test]
+    fn renders_builtin_templates() {
+        let c = context();
+
+        assert_eq!(render("{url}", &c), c.url);
+        assert_eq!(
+            render("![{alt}]({url})", &c),
+            "![a](https://example.com/a.png?x=1&y=2)"
+        );
+        assert_eq!(
+            render(".. image:: {url}\n   :alt: {alt}", &c),
+            ".. image:: https://example.com/a.png?x=1&y=2\n   :alt: a"
+        );
+        assert_eq!(
+            render("[url={url}][img]{thumb}[/img][/url] {filename}", &c),
+            "[url=https://example.com/a.png?x=1&y=2][img]https://example.com/a_thumb.png[/img][/url] a.png"
+        );
+    }
+
+    #[test]
+    fn values_are_not_substituted_twice() {
+        let c = LinkContext {
+            alt: "{url}".to_owned(),
+            ..context()
+        };
+
+        assert_eq!(render("{alt} {unknown}", &c), "{url} {unknown}");
+    }
+
+    #[test]
+    fn html_values_are_escaped() {
+        let c = LinkContext {
+            alt: r#"a" onerror="alert(1)"#.to_owned(),
+            ..context()
+        };
+
+        assert_eq!(
+            render(&find_link_template(&HashMap::new(), "html").unwrap(), &c),
+            r#"<img src="https://example.com/a.png?x=1&amp;y=2" alt="a&quot; onerror=&quot;alert(1)" width="640" height="480">"#
+        );
+    }
+
+    #[test]
+    fn html_omits_empty_size() {
+        let c = LinkContext {
+            width: String::new(),
+            height: String::new(),
+            ..context()
+        };
+
+        assert_eq!(
+            render(&find_link_template(&HashMap::new(), "html").unwrap(), &c),
+            r#"<img src="https://example.com/a.png?x=1&amp;y=2" alt="a">"#
+        );
+        assert_eq!(render("{width}x{height}", &c), "x");
+    }
+
+    #[test]
+    fn file_stem_keeps_dotfiles() {
+        assert_eq!(file_stem("a.tar.gz"), "a.tar");
+        assert_eq!(file_stem(".png"), ".png");
+        assert_eq!(file_stem("a"), "a");
+    }
+}
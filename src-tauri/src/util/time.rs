@@ -1,7 +1,18 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use time::{macros::offset, OffsetDateTime, UtcOffset};
+
 use crate::Up2bResult;
 
+/// time::OffsetDateTime::now_local() 方法在 POSIX 上线程不安全：https://github.com/time-rs/time/issues/457
+/// 直接指定时区为 +8。
+pub const LOCAL_OFFSET: UtcOffset = offset!(+8);
+
+/// 当前的本地时间，时区为 [`LOCAL_OFFSET`]
+pub fn now_local() -> OffsetDateTime {
+    OffsetDateTime::now_utc().to_offset(LOCAL_OFFSET)
+}
+
 pub fn now() -> Up2bResult<Duration> {
     Ok(SystemTime::now().duration_since(UNIX_EPOCH)?)
 }
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    manager::{AllowedImageFormat, UploadResult},
+    upload::upload_image,
+    using_uploader, Up2bError, Up2bResult,
+};
+
+const WATCH_EVENT_NAME: &str = "watch://uploaded";
+
+/// 文件在此时间内没有变化且大小不变时才认为已写入完成
+const DEBOUNCE: Duration = Duration::from_millis(1000);
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+lazy_static! {
+    /// 应用内正在运行的监视任务
+    static ref WATCHING: Mutex<Option<CancellationToken>> = Mutex::new(None);
+}
+
+/// 等待写入完成的文件
+struct Pending {
+    last_event: Instant,
+    size: Option<u64>,
+}
+
+fn is_image(path: &Path, allowed_formats: &[AllowedImageFormat]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(AllowedImageFormat::from_extension)
+        .is_some_and(|f| allowed_formats.contains(&f))
+}
+
+/// 上传新图片，成功后将链接复制到剪贴板
+async fn upload_new_image(app: &AppHandle, image_path: &Path) {
+    let result = match using_uploader().await {
+        Ok((manager, uploader)) => {
+            if !is_image(image_path, &uploader.allowed_formats()) {
+                debug!("图床不支持此格式，忽略：{:?}", image_path);
+                return;
+            }
+
+            upload_image(uploader.as_ref(), &manager, None, 0, image_path, false).await
+        }
+        Err(e) => UploadResult::Error {
+            code: e.as_string(),
+            detail: e,
+        },
+    };
+
+    match &result {
+        UploadResult::Response(r) => {
+            info!("已自动上传：{:?} -> {}", image_path, r.url);
+
+            if let Err(e) = app.clipboard().write_text(r.url.clone()) {
+                error!("复制链接到剪贴板失败：{}", e);
+            }
+        }
+        UploadResult::Error { detail, .. } => {
+            error!("自动上传失败：{:?}, {}", image_path, detail)
+        }
+    }
+
+    let _ = app.emit(WATCH_EVENT_NAME, &result);
+}
+
+type Events = mpsc::UnboundedReceiver<notify::Result<notify::Event>>;
+
+/// 创建监视器并注册全部目录，任一目录无法监视时返回错误
+fn watcher(dirs: &[PathBuf]) -> Up2bResult<(RecommendedWatcher, Events)> {
+    if dirs.is_empty() {
+        return Err(Up2bError::Other("没有需要监视的目录".to_owned()));
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let _ = tx.send(event);
+    })?;
+
+    for dir in dirs.iter() {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        info!("开始监视目录：{:?}", dir);
+    }
+
+    Ok((watcher, rx))
+}
+
+/// 监视目录中新增的图片并自动上传，直到 token 被取消
+pub async fn watch(app: AppHandle, dirs: Vec<PathBuf>, token: CancellationToken) -> Up2bResult<()> {
+    let (watcher, rx) = watcher(&dirs)?;
+
+    run(app, dirs, watcher, rx, token).await;
+
+    Ok(())
+}
+
+/// 处理监视器的事件，watcher 需要保持存活直到结束
+async fn run(
+    app: AppHandle,
+    dirs: Vec<PathBuf>,
+    _watcher: RecommendedWatcher,
+    mut rx: Events,
+    token: CancellationToken,
+) {
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            event = rx.recv() => {
+                let event = match event {
+                    None => break,
+                    Some(Ok(e)) => e,
+                    Some(Err(e)) => {
+                        warn!("监视目录出错：{}", e);
+                        continue;
+                    }
+                };
+
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+
+                for path in event.paths {
+                    if !is_image(&path, &AllowedImageFormat::all()) {
+                        continue;
+                    }
+
+                    trace!("文件变化：{:?}", path);
+
+                    pending
+                        .entry(path)
+                        .and_modify(|p| p.last_event = Instant::now())
+                        .or_insert(Pending { last_event: Instant::now(), size: None });
+                }
+            }
+            _ = interval.tick() => {
+                let mut ready = Vec::new();
+
+                for (path, p) in pending.iter_mut() {
+                    if p.last_event.elapsed() < DEBOUNCE {
+                        continue;
+                    }
+
+                    // 文件已被删除或移走
+                    let size = match tokio::fs::metadata(path).await {
+                        Ok(m) => m.len(),
+                        Err(_) => {
+                            ready.push((path.clone(), false));
+                            continue;
+                        }
+                    };
+
+                    if p.size == Some(size) && size > 0 {
+                        ready.push((path.clone(), true));
+                    } else {
+                        p.size = Some(size);
+                        p.last_event = Instant::now();
+                    }
+                }
+
+                for (path, exists) in ready {
+                    pending.remove(&path);
+
+                    if exists {
+                        upload_new_image(&app, &path).await;
+                    }
+                }
+            }
+        }
+    }
+
+    info!("停止监视目录：{:?}", dirs);
+}
+
+/// 在后台开始监视，已在监视时先停止之前的任务。目录在返回前注册，失败时不影响之前的任务
+pub fn start(app: AppHandle, dirs: Vec<PathBuf>) -> Up2bResult<()> {
+    let (watcher, rx) = watcher(&dirs)?;
+
+    let token = CancellationToken::new();
+
+    if let Some(old) = WATCHING.lock().unwrap().replace(token.clone()) {
+        old.cancel();
+    }
+
+    tauri::async_runtime::spawn(run(app, dirs, watcher, rx, token));
+
+    Ok(())
+}
+
+pub fn stop() {
+    if let Some(token) = WATCHING.lock().unwrap().take() {
+        token.cancel();
+    }
+}
+
+pub fn is_watching() -> bool {
+    WATCHING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|t| !t.is_cancelled())
+}
@@ -1,5 +1,5 @@
 import React, { useState } from 'react'
-import { Card, Image, Spin, Tooltip } from 'antd'
+import { Card, Dropdown, Image, Spin, Tooltip } from 'antd'
 import {
   EyeOutlined,
   CopyOutlined,
@@ -7,8 +7,9 @@ import {
   WarningOutlined,
 } from '@ant-design/icons'
 import { writeText } from '@tauri-apps/plugin-clipboard-manager'
+import { convertFileSrc } from '@tauri-apps/api/core'
 import type { MessageInstance } from 'antd/es/message/interface'
-import { deleteImage } from '~/lib'
+import { deleteImage, getLinkTemplates, renderLink } from '~/lib'
 import './index.scss'
 
 interface ImageCardStatusSuccess {
@@ -29,9 +30,36 @@ interface ImageCardProps {
   status: ImageCardStatusSuccess | ImageCardStatusWarning
 }
 
+// 无法上传的缩略图保存在本地，需要转换为 asset 协议的地址
+const thumbSrc = (thumb: string) =>
+  /^https?:\/\//.test(thumb) ? thumb : convertFileSrc(thumb)
+
 const ImageCard = ({ url, thumb, messageApi, status }: ImageCardProps) => {
   const [visible, setVisible] = useState(false)
   const [deleting, setDeleting] = useState(false)
+  const [templates, setTemplates] = useState<LinkTemplate[]>([])
+
+  // 链接由后端按模板渲染，与命令行输出一致
+  const onCopy = async (template = 'url') => {
+    const image: ImageResponseItem = {
+      url,
+      thumb,
+      deleted_id: status.type === 'success' ? status.deleteId : '',
+    }
+
+    try {
+      await writeText(await renderLink(image, template))
+      messageApi.success('已复制到剪贴板')
+    } catch (e) {
+      messageApi.error(String(e))
+    }
+  }
+
+  const loadTemplates = async (open: boolean) => {
+    if (!open || templates.length) return
+
+    setTemplates(await getLinkTemplates())
+  }
 
   const onDelete = async () => {
     if (status.type === 'warning') return
@@ -62,7 +90,7 @@ const ImageCard = ({ url, thumb, messageApi, status }: ImageCardProps) => {
       <Spin tip="正在删除..." spinning={deleting}>
         <Image
           className="image-list-item"
-          src={thumb ?? url}
+          src={thumb ? thumbSrc(thumb) : url}
           width={150}
           height={180}
           preview={{
@@ -71,12 +99,18 @@ const ImageCard = ({ url, thumb, messageApi, status }: ImageCardProps) => {
             mask: (
               <span className="image-list-actions">
                 <EyeOutlined onClick={() => setVisible(true)} />
-                <CopyOutlined
-                  onClick={async () => {
-                    await writeText(url)
-                    messageApi.success('已复制到剪贴板')
+                <Dropdown
+                  menu={{
+                    items: templates.map((t) => ({
+                      key: t.name,
+                      label: t.name,
+                    })),
+                    onClick: ({ key }) => onCopy(key),
                   }}
-                />
+                  onOpenChange={loadTemplates}
+                >
+                  <CopyOutlined onClick={() => onCopy()} />
+                </Dropdown>
                 {status.type === 'success' ? (
                   <DeleteOutlined onClick={onDelete} />
                 ) : (
@@ -9,8 +9,62 @@ export const updateConfig = async (config: Config) => {
 	await invoke("update_config", { config });
 };
 
-export const uploadImage = async (imagePath: string) => {
-	return await invoke<UploadResult>("upload_image", { imagePath });
+// force 为 true 时即使相同内容的图片已上传过也重新上传
+export const uploadImage = async (imagePath: string, force?: boolean) => {
+	return await invoke<UploadResult>("upload_image", { imagePath, force });
+};
+
+export const uploadImageData = async (
+	data: number[],
+	filename?: string,
+	force?: boolean,
+) => {
+	return await invoke<UploadResult>("upload_image_data", {
+		data,
+		filename,
+		force,
+	});
+};
+
+export const uploadImageUrl = async (url: string, force?: boolean) => {
+	return await invoke<UploadResult>("upload_image_url", { url, force });
+};
+
+export const uploadImageMirror = async (imagePath: string, force?: boolean) => {
+	return await invoke<MirrorUploadResult>("upload_image_mirror", {
+		imagePath,
+		force,
+	});
+};
+
+export const uploadImages = async (
+	imagePaths: string[],
+	concurrency?: number,
+	force?: boolean,
+) => {
+	return await invoke<UploadResult[]>("upload_images", {
+		imagePaths,
+		concurrency,
+		force,
+	});
+};
+
+export const getLinkTemplates = async () => {
+	return await invoke<LinkTemplate[]>("get_link_templates");
+};
+
+export const renderLink = async (
+	image: ImageResponseItem,
+	template?: string,
+	imagePath?: string,
+	alt?: string,
+) => {
+	return await invoke<string>("render_link", {
+		image,
+		template,
+		imagePath,
+		alt,
+	});
 };
 
 export const getAllImages = async () => {
@@ -21,6 +75,58 @@ export const deleteImage = async (deleteId: string) => {
 	return await invoke<DeleteResponse>("delete_image", { deleteId });
 };
 
+export const getUploadHistory = async (query?: HistoryQuery) => {
+	return await invoke<HistoryItem[]>("get_upload_history", { query });
+};
+
+export const deleteUploadHistory = async (
+	id: number,
+	deleteRemote: boolean,
+) => {
+	return await invoke<DeleteResponse>("delete_upload_history", {
+		id,
+		deleteRemote,
+	});
+};
+
+export const clearUploadHistory = async (manager?: ManagerCode) => {
+	await invoke("clear_upload_history", { manager });
+};
+
+export const rewriteDocument = async (options: RewriteOptions) => {
+	return await invoke<RewriteReport>("rewrite_document", { options });
+};
+
+// dirs 为空时使用配置中的目录
+export const startWatching = async (dirs?: string[]) => {
+	await invoke("start_watching", { dirs });
+};
+
+export const stopWatching = async () => {
+	await invoke("stop_watching");
+};
+
+export const isWatching = async () => {
+	return await invoke<boolean>("is_watching");
+};
+
+// config 为空时使用配置中的服务配置
+export const startServer = async (config?: ServerConfig) => {
+	await invoke("start_server", { config });
+};
+
+export const stopServer = async () => {
+	await invoke("stop_server");
+};
+
+export const isServing = async () => {
+	return await invoke<boolean>("is_serving");
+};
+
+export const migrateImages = async (options: MigrateOptions) => {
+	return await invoke<MigrateReport>("migrate_images", { options });
+};
+
 export const getCompressState = async () => {
 	return await invoke<boolean>("compress_state");
 };
@@ -1,16 +1,26 @@
-import React, { useEffect } from "react";
+import React from "react";
 import { Divider, Form, Space, Button, Input, FormRule } from "antd";
 import { areObjectsEqual, clearStorage, updateConfig } from "~/lib";
 import { cleanConfig } from "..";
 import type { MessageInstance } from "antd/es/message/interface";
 
-const initForm: Omit<GitAuthConfig, "type"> = {
-  base_url: "https://api.github.com/repos/",
+/*
+ * github 和 gitlab 有默认接口，gitea 和 forgejo 只能自托管，需要填写接口
+ */
+const defaultBaseUrl: Record<GitManagerKey, string> = {
+  GITHUB: "https://api.github.com",
+  GITLAB: "https://gitlab.com",
+  GITEA: "",
+  FORGEJO: "",
+};
+
+const initForm = (managerKey: GitManagerKey): Omit<GitAuthConfig, "type"> => ({
+  base_url: defaultBaseUrl[managerKey],
   token: "",
   username: "",
   repository: "",
   path: "up2b",
-};
+});
 
 interface GitSettingProps {
   config: Config;
@@ -36,7 +46,13 @@ const GitSetting = ({
   return (
     <Form
       form={form}
-      initialValues={config.auth_config[managerKey] ?? initForm}
+      initialValues={{
+        ...initForm(managerKey),
+        ...config.auth_config[managerKey],
+        base_url:
+          config.auth_config[managerKey]?.base_url ||
+          defaultBaseUrl[managerKey],
+      }}
       onFinish={async (values) => {
         const newConfig = {
           ...config!,
@@ -85,6 +101,10 @@ const GitSetting = ({
         <Form.Item name="path" label="目录">
           <Input placeholder="默认为 up2b" />
         </Form.Item>
+
+        <Form.Item name="branch" label="分支">
+          <Input placeholder="默认为仓库的默认分支" />
+        </Form.Item>
       </Space>
       <Divider />
 
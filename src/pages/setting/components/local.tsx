@@ -0,0 +1,97 @@
+import React from "react";
+import { Divider, Form, Space, Button, Input, FormRule } from "antd";
+import { areObjectsEqual, clearStorage, updateConfig } from "~/lib";
+import { cleanConfig } from "..";
+import type { MessageInstance } from "antd/es/message/interface";
+
+const initForm: Omit<LocalAuthConfig, "type"> = {
+  directory: "",
+  url_prefix: "",
+};
+
+interface LocalSettingProps {
+  config: Config;
+  defaultConfig: Config;
+  setConfig: React.Dispatch<React.SetStateAction<Config | null>>;
+  setDefaultConfig: React.Dispatch<React.SetStateAction<Config | null>>;
+  managerKey: LocalManagerKey;
+  message: MessageInstance;
+}
+
+const LocalSetting = ({
+  config,
+  defaultConfig,
+  setConfig,
+  setDefaultConfig,
+  managerKey,
+  message,
+}: LocalSettingProps) => {
+  const [form] = Form.useForm();
+
+  const rules: FormRule[] = [{ required: true }];
+
+  return (
+    <Form
+      form={form}
+      initialValues={{ ...initForm, ...config.auth_config[managerKey] }}
+      onFinish={async (values) => {
+        const newConfig = {
+          ...config!,
+          auth_config: {
+            ...config.auth_config,
+            [managerKey]: { ...values, type: "LOCAL" },
+          },
+        };
+
+        cleanConfig(newConfig);
+
+        try {
+          await updateConfig(newConfig);
+          setDefaultConfig(config);
+          setConfig(newConfig);
+
+          clearStorage(managerKey);
+
+          message.success("已保存 " + managerKey + " 配置");
+        } catch (e) {
+          message.error(String(e));
+        }
+      }}
+    >
+      <Form.Item name="directory" label="目录" rules={rules}>
+        <Input placeholder="保存图片的目录" />
+      </Form.Item>
+
+      <Form.Item
+        name="url_prefix"
+        label="地址前缀"
+        rules={[...rules, { type: "url", warningOnly: true }]}
+      >
+        <Input placeholder="访问图片的地址前缀" />
+      </Form.Item>
+      <Divider />
+
+      <Form.Item
+        style={{
+          display: "flex",
+          alignItems: "center",
+          justifyContent: "center",
+        }}
+      >
+        <Space>
+          <Button
+            onClick={() => location.reload()}
+            disabled={areObjectsEqual(defaultConfig, config)}
+          >
+            取消
+          </Button>
+          <Button type="primary" htmlType="submit">
+            保存
+          </Button>
+        </Space>
+      </Form.Item>
+    </Form>
+  );
+};
+
+export default LocalSetting;
@@ -0,0 +1,131 @@
+import React from "react";
+import { Divider, Form, Space, Button, Input, FormRule, Switch } from "antd";
+import { areObjectsEqual, clearStorage, updateConfig } from "~/lib";
+import { cleanConfig } from "..";
+import type { MessageInstance } from "antd/es/message/interface";
+
+const initForm: Omit<S3AuthConfig, "type"> = {
+  endpoint: "",
+  region: "",
+  bucket: "",
+  access_key: "",
+  secret_key: "",
+  path_style: true,
+};
+
+interface S3SettingProps {
+  config: Config;
+  defaultConfig: Config;
+  setConfig: React.Dispatch<React.SetStateAction<Config | null>>;
+  setDefaultConfig: React.Dispatch<React.SetStateAction<Config | null>>;
+  managerKey: S3ManagerKey;
+  message: MessageInstance;
+}
+
+const S3Setting = ({
+  config,
+  defaultConfig,
+  setConfig,
+  setDefaultConfig,
+  managerKey,
+  message,
+}: S3SettingProps) => {
+  const [form] = Form.useForm();
+
+  const rules: FormRule[] = [{ required: true }];
+
+  return (
+    <Form
+      form={form}
+      initialValues={{ ...initForm, ...config.auth_config[managerKey] }}
+      onFinish={async (values) => {
+        const newConfig = {
+          ...config!,
+          auth_config: {
+            ...config.auth_config,
+            [managerKey]: { ...values, type: "S3" },
+          },
+        };
+
+        cleanConfig(newConfig);
+
+        try {
+          await updateConfig(newConfig);
+          setDefaultConfig(config);
+          setConfig(newConfig);
+
+          clearStorage(managerKey);
+
+          message.success("已保存 " + managerKey + " 配置");
+        } catch (e) {
+          message.error(String(e));
+        }
+      }}
+    >
+      <Form.Item
+        name="endpoint"
+        label="接口"
+        rules={[...rules, { type: "url", warningOnly: true }]}
+      >
+        <Input placeholder="https://s3.amazonaws.com" />
+      </Form.Item>
+
+      <Space wrap>
+        <Form.Item name="region" label="区域" rules={rules}>
+          <Input placeholder="us-east-1" />
+        </Form.Item>
+
+        <Form.Item name="bucket" label="存储桶" rules={rules}>
+          <Input />
+        </Form.Item>
+
+        <Form.Item name="access_key" label="ACCESS KEY" rules={rules}>
+          <Input />
+        </Form.Item>
+
+        <Form.Item name="secret_key" label="SECRET KEY" rules={rules}>
+          <Input.Password />
+        </Form.Item>
+
+        <Form.Item name="prefix" label="前缀">
+          <Input placeholder="对象键前缀" />
+        </Form.Item>
+
+        <Form.Item name="path_style" label="路径风格" valuePropName="checked">
+          <Switch />
+        </Form.Item>
+      </Space>
+
+      <Form.Item
+        name="public_url"
+        label="访问地址"
+        rules={[{ type: "url", warningOnly: true }]}
+      >
+        <Input placeholder="为空时使用接口和存储桶拼接" />
+      </Form.Item>
+      <Divider />
+
+      <Form.Item
+        style={{
+          display: "flex",
+          alignItems: "center",
+          justifyContent: "center",
+        }}
+      >
+        <Space>
+          <Button
+            onClick={() => location.reload()}
+            disabled={areObjectsEqual(defaultConfig, config)}
+          >
+            取消
+          </Button>
+          <Button type="primary" htmlType="submit">
+            保存
+          </Button>
+        </Space>
+      </Form.Item>
+    </Form>
+  );
+};
+
+export default S3Setting;
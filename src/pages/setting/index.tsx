@@ -26,6 +26,8 @@ import AddCustom from "./components/add.tsx";
 import ApiSettingForm from "./components/api/form.tsx";
 import CheveretoSetting from "./components/chevereto.tsx";
 import GitSetting from "./components/git.tsx";
+import S3Setting from "./components/s3.tsx";
+import LocalSetting from "./components/local.tsx";
 
 /*
  * 删除非 USING 图床 keys 数量等于 1 的配置(只一个 type)
@@ -208,25 +210,39 @@ const Setting = ({ config, setConfig }: SettingProps) => {
           />
         );
       case "GIT":
-        const key = config!.using as InferKeyType<typeof imageBedKind>;
-        const form = {
-          ...config!,
-          auth_config: {
-            ...config.auth_config,
-            [key]: {
-              ...config.auth_config.GITHUB!,
-              type: "GIT",
-              base_url: "https://api.github.com",
-            },
-          },
-        };
+        const gitKey = config!.using as InferKeyType<typeof imageBedKind>;
         return (
           <GitSetting
-            config={form}
+            key={gitKey}
+            config={config!}
+            setConfig={setConfig}
+            setDefaultConfig={setDefaultConfig}
+            defaultConfig={defaultConfig!}
+            managerKey={gitKey}
+            message={messageApi}
+          />
+        );
+      case "S3":
+        const s3Key = config!.using as InferKeyType<typeof imageBedKind>;
+        return (
+          <S3Setting
+            config={config!}
+            setConfig={setConfig}
+            setDefaultConfig={setDefaultConfig}
+            defaultConfig={defaultConfig!}
+            managerKey={s3Key}
+            message={messageApi}
+          />
+        );
+      case "LOCAL":
+        const localKey = config!.using as InferKeyType<typeof imageBedKind>;
+        return (
+          <LocalSetting
+            config={config!}
             setConfig={setConfig}
             setDefaultConfig={setDefaultConfig}
             defaultConfig={defaultConfig!}
-            managerKey={key}
+            managerKey={localKey}
             message={messageApi}
           />
         );
@@ -128,7 +128,8 @@ const Upload = () => {
       );
     }
 
-    let automaticCompression = compressState && config.automatic_compression;
+    let automaticCompression =
+      compressState && (config.automatic_compression || !!config.optimize);
 
     let compressListener: UnlistenFn | null = null;
     if (automaticCompression) {
@@ -136,11 +137,12 @@ const Upload = () => {
         "upload://compress",
         (e) => {
           if (e.payload.type === "END") {
-            const { filename, original, compressed } = e.payload;
+            const { filename, original, compressed, quality, width, height } =
+              e.payload;
             messageApi.success(
               `${filename} 已压缩：${formatBytes(original)} -> ${formatBytes(
                 compressed,
-              )}`,
+              )}，质量 ${quality}，尺寸 ${width} x ${height}`,
             );
           }
 
@@ -15,6 +15,8 @@ interface ApiAuthConfig {
   type: 'API'
   token: string
   api: ApiConfig
+  // 上传后的文件名模板
+  name_template?: string
 }
 
 interface ApiConfig {
@@ -6,6 +6,10 @@ interface GitAuthConfig {
   repository: string
   // 保存目录,默认为 up2b
   path?: string
+  // 分支，为空时使用仓库的默认分支
+  branch?: string
+  // 上传后的文件名模板，默认为 {stem}_{timestamp}.{ext}
+  name_template?: string
 }
 
 type GithubAuthConfig = Omit<GitAuthConfig, 'base_url'>
@@ -1,6 +1,15 @@
-type ManagerKind = 'API' | 'CHEVERETO' | 'GIT'
-
-type ManagerCode = 'SMMS' | 'IMGSE' | 'IMGTG' | 'GITHUB'
+type ManagerKind = 'API' | 'CHEVERETO' | 'GIT' | 'S3' | 'LOCAL'
+
+type ManagerCode =
+  | 'SMMS'
+  | 'IMGSE'
+  | 'IMGTG'
+  | 'GITHUB'
+  | 'GITLAB'
+  | 'GITEA'
+  | 'FORGEJO'
+  | 'S3'
+  | 'LOCAL'
 
 type _APIKey<T extends ManagerCode> = T extends 'SMMS' ? T : never
 
@@ -12,18 +21,36 @@ type _CheveretoKey<T extends ManagerCode> = T extends 'IMGSE' | 'IMGTG'
 
 type CheveretoManagerKey = _CheveretoKey<ManagerCode>
 
-type _GitManagerKey<T extends ManagerCode> = T extends 'GITHUB' ? T : never
+type _GitManagerKey<T extends ManagerCode> = T extends
+  | 'GITHUB'
+  | 'GITLAB'
+  | 'GITEA'
+  | 'FORGEJO'
+  ? T
+  : never
 type GitManagerKey = _GitManagerKey<ManagerCode>
 
+type _S3ManagerKey<T extends ManagerCode> = T extends 'S3' ? T : never
+type S3ManagerKey = _S3ManagerKey<ManagerCode>
+
+type _LocalManagerKey<T extends ManagerCode> = T extends 'LOCAL' ? T : never
+type LocalManagerKey = _LocalManagerKey<ManagerCode>
+
 type InferKeyType<
   T extends
   | ApiAuthConfig['type']
   | CheveretoAuthConfig['type']
-  | GitAuthConfig['type'],
+  | GitAuthConfig['type']
+  | S3AuthConfig['type']
+  | LocalAuthConfig['type'],
 > = T extends 'API'
   ? APIManagerKey
   : T extends 'CHEVERETO'
   ? CheveretoManagerKey
+  : T extends 'S3'
+  ? S3ManagerKey
+  : T extends 'LOCAL'
+  ? LocalManagerKey
   : GitManagerKey
 
 type Extra = Record<string, string>
@@ -70,17 +97,83 @@ type InferAuthConfigKind<K extends ManagerCode> = K extends APIManagerKey
   ? CheveretoAuthConfig
   : K extends GitManagerKey
   ? GitAuthConfig
+  : K extends S3ManagerKey
+  ? S3AuthConfig
+  : K extends LocalManagerKey
+  ? LocalAuthConfig
   : never
 
 type AuthConfigKinds = {
   [K in ManagerCode]?: InferAuthConfigKind<K>
 }
 
+interface ServerConfig {
+  port: number
+  // 为空时使用配置中的 token，没有时自动生成
+  token: string
+  auto_start: boolean
+}
+
+interface OptimizeConfig {
+  format: 'JPEG' | 'WEBP' | 'AVIF'
+  quality: number
+  max_dimension?: number
+}
+
+interface ThumbnailConfig {
+  max_edge?: number
+  format?: 'JPEG' | 'WEBP' | 'AVIF'
+  quality?: number
+  upload?: boolean
+}
+
+type CompressedFormat = 'JPEG' | 'WEBP' | 'AVIF' | 'PNG'
+
+interface DecodeLimits {
+  max_alloc?: number
+  max_width?: number
+  max_height?: number
+}
+
+interface TextWatermark {
+  type: 'TEXT'
+  text: string
+  font: string
+  color?: [number, number, number]
+}
+
+interface ImageWatermark {
+  type: 'IMAGE'
+  path: string
+}
+
+interface WatermarkConfig {
+  kind: TextWatermark | ImageWatermark
+  position?: 'TOP_LEFT' | 'TOP_RIGHT' | 'BOTTOM_LEFT' | 'BOTTOM_RIGHT' | 'CENTER'
+  opacity?: number
+  margin?: number
+  scale?: number
+  min_width?: number
+  min_height?: number
+}
+
 interface Config {
   using: ManagerCode
   use_proxy: boolean
   automatic_compression: boolean
+  compression_margin?: number
+  compressed_formats?: Partial<Record<ManagerCode, CompressedFormat>>
+  optimize?: OptimizeConfig
+  watermark?: WatermarkConfig
+  thumbnail?: ThumbnailConfig
+  decode_limits?: DecodeLimits
   proxy?: Proxy
+  mirrors?: ManagerCode[]
+  fallbacks?: ManagerCode[]
+  strip_metadata?: ManagerCode[]
+  watch_dirs?: string[]
+  server?: ServerConfig
+  link_templates?: Record<string, string>
   auth_config: AuthConfigKinds
 }
 
@@ -0,0 +1,9 @@
+interface LocalAuthConfig {
+  type: 'LOCAL'
+  // 保存图片的目录
+  directory: string
+  // 访问图片的地址前缀
+  url_prefix: string
+  // 上传后的文件名模板，默认为 {stem}_{timestamp}.{ext}
+  name_template?: string
+}
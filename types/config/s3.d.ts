@@ -0,0 +1,16 @@
+interface S3AuthConfig {
+  type: 'S3'
+  endpoint: string
+  region: string
+  bucket: string
+  access_key: string
+  secret_key: string
+  // 对象键前缀
+  prefix?: string
+  // 是否使用路径风格的地址，默认为 true
+  path_style?: boolean
+  // 公开访问地址，为空时使用 endpoint 拼接
+  public_url?: string
+  // 上传后的文件名模板，默认为 {stem}_{timestamp}.{ext}
+  name_template?: string
+}
@@ -13,6 +13,37 @@ interface ImageResponseItem {
   deleted_id: string
   url: string
   thumb?: string
+  // 上传图片的图床，只在上传结果中存在
+  manager?: ManagerCode
+}
+
+interface HistoryItem {
+  id: number
+  manager: ManagerCode
+  url: string
+  deleted_id: string
+  thumb?: string
+  // 上传到图床的缩略图的删除 id
+  thumb_deleted_id?: string
+  filename: string
+  size: number
+  hash: string
+  // 上传时图片处理配置的哈希
+  pipeline_hash: string
+  created_at: number
+}
+
+interface HistoryQuery {
+  manager?: ManagerCode
+  keyword?: string
+  offset?: number
+  limit?: number
+}
+
+interface LinkTemplate {
+  name: string
+  template: string
+  builtin: boolean
 }
 
 interface UploadResponse extends ImageResponseItem {
@@ -20,3 +51,65 @@ interface UploadResponse extends ImageResponseItem {
 }
 
 type UploadResult = UploadResponse | BaseError
+
+interface MirrorError {
+  manager: ManagerCode
+  detail: string
+  code: string
+}
+
+interface MirrorUploadResult {
+  primary: ImageResponseItem | null
+  fallbacks: ImageResponseItem[]
+  errors: MirrorError[]
+}
+
+interface MigrateOptions {
+  source: ManagerCode
+  target: ManagerCode
+  mapping_file?: string
+  rewrite_dir?: string
+  concurrency?: number
+}
+
+interface MigrateFailure {
+  url: string
+  detail: string
+  code: string
+}
+
+interface MigrateReport {
+  total: number
+  mapping: Record<string, string>
+  failures: MigrateFailure[]
+  mapping_file: string
+  rewritten: string[]
+}
+
+interface MigrateProgress {
+  done: number
+  total: number
+  url: string
+  success: boolean
+}
+
+interface RewriteOptions {
+  path: string
+  dry_run?: boolean
+  backup?: boolean
+  force?: boolean
+}
+
+interface RewrittenImage {
+  source: string
+  url: string | null
+  error: string | null
+  code: string | null
+}
+
+interface RewriteReport {
+  path: string
+  images: RewrittenImage[]
+  diff: string
+  backup: string | null
+}
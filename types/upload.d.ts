@@ -34,6 +34,9 @@ interface EndCompressEvent {
   filename: string
   original: number
   compressed: number
+  quality: number
+  width: number
+  height: number
 }
 
 type CompressEvent = NoCompressEvent | StartCompressEvent | EndCompressEvent